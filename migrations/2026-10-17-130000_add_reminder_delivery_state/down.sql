@@ -0,0 +1,4 @@
+ALTER TABLE reminders
+    DROP COLUMN failed_attempts,
+    DROP COLUMN retry_at,
+    DROP COLUMN failed;
@@ -0,0 +1,4 @@
+ALTER TABLE reminders
+    ADD COLUMN failed_attempts integer NOT NULL DEFAULT 0,
+    ADD COLUMN retry_at timestamptz,
+    ADD COLUMN failed boolean NOT NULL DEFAULT false;
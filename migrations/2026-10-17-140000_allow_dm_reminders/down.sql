@@ -0,0 +1,3 @@
+-- DM reminders have no guild to fall back to
+DELETE FROM reminders WHERE guild_id IS NULL;
+ALTER TABLE reminders ALTER COLUMN guild_id SET NOT NULL;
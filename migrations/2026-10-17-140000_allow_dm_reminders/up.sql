@@ -0,0 +1 @@
+ALTER TABLE reminders ALTER COLUMN guild_id DROP NOT NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE users DROP COLUMN reminders_from_others;
+ALTER TABLE reminders
+    DROP COLUMN target_user_id,
+    DROP COLUMN target_role_id;
@@ -0,0 +1,4 @@
+ALTER TABLE reminders
+    ADD COLUMN target_user_id bigint,
+    ADD COLUMN target_role_id bigint;
+ALTER TABLE users ADD COLUMN reminders_from_others boolean NOT NULL DEFAULT true;
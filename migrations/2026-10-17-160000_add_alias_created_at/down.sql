@@ -0,0 +1 @@
+ALTER TABLE aliases DROP COLUMN created_at;
@@ -0,0 +1,2 @@
+-- Existing aliases have no record of when they were made, so count them as made now
+ALTER TABLE aliases ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1 @@
+DROP INDEX aliases_guild_id_command_name_key;
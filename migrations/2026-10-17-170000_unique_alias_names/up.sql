@@ -0,0 +1,35 @@
+-- Keep the oldest of any aliases that share a name, renaming the rest so they are not lost
+DO $$
+DECLARE
+    duplicate RECORD;
+    new_name TEXT;
+    suffix INTEGER;
+BEGIN
+    FOR duplicate IN
+        SELECT a.alias_id, a.guild_id, a.command_name FROM aliases a
+        WHERE EXISTS (
+            SELECT 1 FROM aliases b
+            WHERE b.guild_id = a.guild_id
+            AND lower(b.command_name) = lower(a.command_name)
+            AND b.alias_id < a.alias_id
+        )
+        ORDER BY a.alias_id
+    LOOP
+        -- The renamed alias must not clash with one that already has the new name
+        new_name := duplicate.command_name || '_' || duplicate.alias_id;
+        suffix := 1;
+        WHILE EXISTS (
+            SELECT 1 FROM aliases
+            WHERE guild_id = duplicate.guild_id
+            AND lower(command_name) = lower(new_name)
+        ) LOOP
+            new_name := duplicate.command_name || '_' || duplicate.alias_id || '_' || suffix;
+            suffix := suffix + 1;
+        END LOOP;
+
+        UPDATE aliases SET command_name = new_name WHERE alias_id = duplicate.alias_id;
+    END LOOP;
+END
+$$;
+
+CREATE UNIQUE INDEX aliases_guild_id_command_name_key ON aliases (guild_id, lower(command_name));
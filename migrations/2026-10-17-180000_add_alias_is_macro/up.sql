@@ -0,0 +1 @@
+ALTER TABLE aliases ADD COLUMN is_macro boolean NOT NULL DEFAULT false;
@@ -0,0 +1,4 @@
+DROP INDEX aliases_guild_id_command_key_key;
+CREATE UNIQUE INDEX aliases_guild_id_command_name_key ON aliases (guild_id, lower(command_name));
+
+ALTER TABLE aliases DROP COLUMN command_key;
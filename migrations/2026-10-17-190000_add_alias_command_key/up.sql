@@ -0,0 +1,53 @@
+-- Matching key for command_name, the same as `util::compatibility_case_fold`: the name
+-- compatibility decomposed and lowercased, with the case foldings lower() does not do. lower()
+-- follows the database's locale, so this needs a UTF-8 one
+CREATE FUNCTION pg_temp.alias_command_key(name text) RETURNS text AS $$
+    SELECT normalize(translate(replace(lower(normalize(name, NFKD)), 'ß', 'ss'), 'ςſ', 'σs'), NFKD)
+$$ LANGUAGE SQL IMMUTABLE;
+
+ALTER TABLE aliases ADD COLUMN command_key text;
+
+DROP INDEX aliases_guild_id_command_name_key;
+
+-- Keep the oldest of any aliases whose names now have the same key, renaming the rest as in
+-- unique_alias_names
+DO $$
+DECLARE
+    entry RECORD;
+    new_name TEXT;
+    suffix INTEGER;
+BEGIN
+    FOR entry IN SELECT alias_id, guild_id, command_name FROM aliases ORDER BY alias_id LOOP
+        new_name := entry.command_name;
+        IF EXISTS (
+            SELECT 1 FROM aliases
+            WHERE guild_id = entry.guild_id
+            AND command_key = pg_temp.alias_command_key(new_name)
+        ) THEN
+            -- The renamed alias must not clash with any other, keyed yet or not
+            new_name := entry.command_name || '_' || entry.alias_id;
+            suffix := 1;
+            WHILE EXISTS (
+                SELECT 1 FROM aliases
+                WHERE guild_id = entry.guild_id
+                AND alias_id <> entry.alias_id
+                AND coalesce(command_key, pg_temp.alias_command_key(command_name))
+                    = pg_temp.alias_command_key(new_name)
+            ) LOOP
+                new_name := entry.command_name || '_' || entry.alias_id || '_' || suffix;
+                suffix := suffix + 1;
+            END LOOP;
+        END IF;
+
+        UPDATE aliases
+        SET command_name = new_name, command_key = pg_temp.alias_command_key(new_name)
+        WHERE alias_id = entry.alias_id;
+    END LOOP;
+END
+$$;
+
+ALTER TABLE aliases ALTER COLUMN command_key SET NOT NULL;
+
+CREATE UNIQUE INDEX aliases_guild_id_command_key_key ON aliases (guild_id, command_key);
+
+DROP FUNCTION pg_temp.alias_command_key;
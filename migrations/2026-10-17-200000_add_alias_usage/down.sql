@@ -0,0 +1,3 @@
+ALTER TABLE aliases
+    DROP COLUMN use_count,
+    DROP COLUMN last_used_at;
@@ -0,0 +1,3 @@
+ALTER TABLE aliases
+    ADD COLUMN use_count bigint NOT NULL DEFAULT 0,
+    ADD COLUMN last_used_at timestamptz;
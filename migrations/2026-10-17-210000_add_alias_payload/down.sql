@@ -0,0 +1,3 @@
+ALTER TABLE aliases
+    DROP COLUMN attachment_urls,
+    DROP COLUMN embed_json;
@@ -0,0 +1,3 @@
+ALTER TABLE aliases
+    ADD COLUMN attachment_urls text[] NOT NULL DEFAULT '{}',
+    ADD COLUMN embed_json text;
@@ -0,0 +1,10 @@
+-- Only guild aliases can be kept
+DELETE FROM aliases WHERE scope <> 'guild';
+
+DROP INDEX aliases_global_command_key_key;
+DROP INDEX aliases_personal_command_key_key;
+
+ALTER TABLE aliases
+    DROP CONSTRAINT aliases_scope_check,
+    ALTER COLUMN guild_id SET NOT NULL,
+    DROP COLUMN scope;
@@ -0,0 +1,13 @@
+-- Personal aliases belong to their owner and global ones to the bot, so neither has a guild
+ALTER TABLE aliases
+    ADD COLUMN scope text NOT NULL DEFAULT 'guild',
+    ALTER COLUMN guild_id DROP NOT NULL,
+    ADD CONSTRAINT aliases_scope_check CHECK (
+        (scope = 'guild' AND guild_id IS NOT NULL)
+        OR (scope IN ('personal', 'global') AND guild_id IS NULL)
+    );
+
+CREATE UNIQUE INDEX aliases_personal_command_key_key ON aliases (user_id, command_key)
+    WHERE scope = 'personal';
+CREATE UNIQUE INDEX aliases_global_command_key_key ON aliases (command_key)
+    WHERE scope = 'global';
@@ -0,0 +1,9 @@
+CREATE TABLE guild_settings (
+    guild_id bigint PRIMARY KEY,
+    -- Who may add, edit and remove aliases: 'everyone', 'role' or 'administrators'
+    alias_permission text NOT NULL DEFAULT 'everyone',
+    alias_role_id bigint,
+    max_aliases_per_user integer,
+    max_alias_length integer,
+    CHECK ((alias_permission = 'role') = (alias_role_id IS NOT NULL))
+);
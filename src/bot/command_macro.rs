@@ -0,0 +1,138 @@
+use crate::util::compatibility_case_fold;
+use log::{error, info};
+use serenity::async_trait;
+use serenity::client::Context;
+use serenity::framework::Framework;
+use serenity::model::prelude::*;
+use serenity::prelude::TypeMapKey;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// How many command macro aliases can expand into each other from a single message
+const MAX_MACRO_DEPTH: usize = 5;
+
+/// Lets the framework be shared between the client and command macros
+pub struct SharedFramework(pub Arc<dyn Framework>);
+
+#[async_trait]
+impl Framework for SharedFramework {
+    async fn dispatch(&self, ctx: Context, msg: Message) {
+        self.0.dispatch(ctx, msg).await;
+    }
+}
+
+/// Dispatches the commands that command macro aliases expand into
+pub struct CommandMacros {
+    framework: Arc<dyn Framework>,
+    prefix: String,
+    /// The macros currently being expanded for each message, outermost first
+    expansions: Mutex<HashMap<MessageId, Vec<String>>>,
+}
+
+pub struct CommandMacrosContainer;
+
+impl TypeMapKey for CommandMacrosContainer {
+    type Value = Arc<CommandMacros>;
+}
+
+impl CommandMacros {
+    pub fn new(framework: Arc<dyn Framework>, prefix: String) -> Self {
+        Self {
+            framework,
+            prefix,
+            expansions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `command` as if the author of `msg` had typed it, as the expansion of alias `name`
+    pub async fn run(&self, ctx: &Context, msg: &Message, name: &str, command: &str) {
+        let key = compatibility_case_fold(name);
+
+        let refusal = {
+            let mut expansions = self.expansions.lock().await;
+            let chain = expansions.entry(msg.id).or_default();
+
+            let looped = chain.contains(&key);
+            chain.push(key);
+
+            if looped {
+                Some(format!(
+                    "Alias `{}` expands into itself: {}",
+                    name,
+                    chain.join(" → ")
+                ))
+            } else if chain.len() > MAX_MACRO_DEPTH {
+                Some(format!(
+                    "Alias `{}` nests more than {} aliases deep",
+                    chain[0], MAX_MACRO_DEPTH
+                ))
+            } else {
+                None
+            }
+        };
+
+        if let Some(refusal) = refusal {
+            info!("Refusing to expand alias '{}': {}", name, refusal);
+            if let Err(e) = msg.reply(ctx, refusal).await {
+                error!(
+                    "Failed to send alias expansion error for '{}': {:?}",
+                    name, e
+                );
+            }
+        } else {
+            // Dispatching a copy of the message runs the usual hooks, so errors are reported as
+            // they would be if the command had been typed directly
+            let mut expanded = msg.clone();
+            expanded.content = prefixed_command(&self.prefix, command);
+            self.framework.dispatch(ctx.clone(), expanded).await;
+        }
+
+        let mut expansions = self.expansions.lock().await;
+        if let Some(chain) = expansions.get_mut(&msg.id) {
+            chain.pop();
+            if chain.is_empty() {
+                expansions.remove(&msg.id);
+            }
+        }
+    }
+}
+
+/// The message content that runs `command`, which may or may not already start with `prefix`
+fn prefixed_command(prefix: &str, command: &str) -> String {
+    let command = command.trim();
+    let command = command.strip_prefix(prefix).unwrap_or(command);
+    format!("{}{}", prefix, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{self, TemplateContext};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn prefix_is_added_once() {
+        assert_eq!(prefixed_command("!", "ping"), "!ping");
+        assert_eq!(prefixed_command("!", " !ping "), "!ping");
+    }
+
+    #[test]
+    fn mentions_reach_the_command_unchanged() {
+        let ctx = TemplateContext {
+            args: "<@123> take the bins out",
+            author: "alice",
+            channel: "<#1234>",
+        };
+        let command = template::render_command(
+            "remind {1} in 1h {rest}",
+            &ctx,
+            &mut StdRng::seed_from_u64(0),
+        );
+        assert_eq!(
+            prefixed_command("!", &command),
+            "!remind <@123> in 1h <@123> take the bins out"
+        );
+    }
+}
@@ -1,53 +1,209 @@
-use crate::{models, schema, util};
-use diesel::{insert_into, RunQueryDsl};
+use crate::{models, schema, template, util};
+use anyhow::{anyhow, Context as _};
+use chrono::{Duration, Utc};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::{insert_into, Connection, RunQueryDsl};
+use figment::providers::{Format, Toml};
+use figment::Figment;
 use log::debug;
+use models::alias::AliasScope;
+use models::guild_settings::{AliasPermission, GuildSettings};
+use serde_derive::{Deserialize, Serialize};
 use serenity::framework::standard::macros::{command, group};
-use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::{Args, CommandError, CommandResult};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
+use serenity::utils::{parse_role, parse_username};
 use std::ops::Deref;
 
+const ALIASES_PER_PAGE: i64 = 20;
+const MAX_SEARCH_RESULTS: i64 = 20;
+const MAX_TOP_ALIASES: i64 = 10;
+const MAX_UNUSED_ALIASES: i64 = 20;
+const DEFAULT_UNUSED_DAYS: i64 = 30;
+/// Largest alias file that can be imported
+const MAX_IMPORT_SIZE: u64 = 1024 * 1024;
+const MAX_LISTED_CONFLICTS: usize = 20;
+/// Highest per-member alias limit a guild can set
+const MAX_ALIASES_PER_USER_LIMIT: u32 = 10_000;
+const USAGE: &str = "Usage:
+`alias add <name> <text>` adds an alias to this server
+`alias add <name> [text]` in reply to a message adds an alias that also sends its attachments and embed
+`alias add --command <name> <command>` adds an alias that runs a bot command
+`alias add --personal <name> <text>` adds an alias only you can use, in any server or DM
+`alias add --global <name> <text>` adds an alias anyone can use anywhere (bot owners only)
+`alias edit <name> <text>` changes the text of an alias you own
+`alias remove <name>` removes an alias you own
+`alias list [@owner] [page]` lists the aliases you can use here
+`alias info <name>` shows who made an alias and when
+`alias settings` shows who can add aliases in this server and the limits on them, which administrators can change
+Your personal aliases are used before this server's, which are used before global ones. Add `--personal`, `--server` or `--global` before the name to pick one with `edit`, `remove` or `info`
+`alias search <text>` finds aliases whose name or text contains some text
+`alias top` lists the most used aliases
+`alias unused [days]` lists aliases that have not been used recently
+`alias stats [@owner]` shows how many aliases someone has and how often they are used
+`alias prune --unused-for <days>` removes aliases that have not been used recently (administrators only)
+`alias export` uploads all the aliases in this server as a JSON file
+`alias import [--skip|--overwrite]` adds the aliases in an attached JSON or TOML file, skipping or replacing ones that already exist (administrators only)";
+
 #[group]
 #[commands(alias)]
 pub struct Alias;
 
 #[command]
-#[sub_commands(add, remove)]
-async fn alias(_ctx: &Context, _msg: &Message, mut _args: Args) -> CommandResult {
+#[sub_commands(
+    add, edit, remove, list, info, search, top, unused, stats, prune, export, import, settings
+)]
+async fn alias(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(ctx, USAGE).await?;
+
     Ok(())
 }
 
 #[command]
 async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (scope_flag, is_macro) = take_flags(&mut args, true)?;
     let command_name = args.quoted().single::<String>()?;
-    let command_text = args.rest().to_string();
-    let GuildId(guild_id) = msg.guild_id.ok_or("Must be used in a server")?;
+    let mut command_text = args.rest().to_string();
+    // Aliases added in DMs can only be personal or global
+    let scope = match (scope_flag, msg.guild_id) {
+        (None, None) => AliasScope::Personal,
+        (flag, _) => scope_for(flag.unwrap_or(ScopeFlag::Server), msg)?,
+    };
+    if scope == AliasScope::Global && !util::user_is_bot_owner(ctx, msg.author.id.0).await {
+        return Err("Only the bot owner can add global aliases".into());
+    }
+
+    // Replying to a message makes an alias of its attachments and embed, and its text unless
+    // some was given
+    let (attachment_urls, embed) = match &msg.referenced_message {
+        Some(replied) => {
+            if command_text.trim().is_empty() {
+                command_text = replied.content.clone();
+            }
+            let attachment_urls = replied
+                .attachments
+                .iter()
+                .map(|a| a.url.clone())
+                .collect::<Vec<_>>();
+            // Other kinds of embed are generated by Discord from links, which are in the text
+            let embed = replied.embeds.iter().find(|e| e.kind == "rich");
+            (attachment_urls, embed)
+        }
+        None => (Vec::new(), None),
+    };
+    if is_macro && (!attachment_urls.is_empty() || embed.is_some()) {
+        return Err("Command aliases cannot have attachments or embeds".into());
+    }
+    if command_text.trim().is_empty() && attachment_urls.is_empty() && embed.is_none() {
+        return Err(
+            "Usage: `alias add <name> <text>`, or reply to a message with `alias add <name>`"
+                .into(),
+        );
+    }
+
+    if let AliasScope::Guild(guild_id) = scope {
+        let settings = check_guild_permission(ctx, msg, guild_id).await?;
+        check_length(&settings, &command_text)?;
+
+        if let Some(max) = settings.max_aliases_per_user {
+            let (count, _) = {
+                let conn = util::get_conn(ctx).await;
+                let conn = conn.lock().await;
+
+                models::alias::Alias::totals_for_user(conn.deref(), guild_id, msg.author.id.0)?
+            };
+            if count >= max as usize {
+                return Err(format!(
+                    "You already have {} alias(es) in this server, which is the most allowed",
+                    count
+                )
+                .into());
+            }
+        }
+    }
 
     let message = {
         let conn = util::get_conn(ctx).await;
         let conn = conn.lock().await;
 
         let user = models::user::User::get_or_create(conn.deref(), msg.author.id.0)?;
-        let alias = models::alias::Alias::new(user.user_id, guild_id, command_name, command_text);
 
-        let query_result = {
-            use schema::aliases::dsl::*;
-            insert_into(aliases).values(alias).execute(conn.deref())
-        };
+        if let Some(existing) = models::alias::Alias::find_in_scope(
+            conn.deref(),
+            &command_name,
+            scope,
+            msg.author.id.0,
+        )? {
+            format!(
+                "{} `{}` already exists, owned by <@{}>",
+                describe_kind(&existing),
+                existing.command_name,
+                existing.user_id
+            )
+        } else {
+            let mut alias =
+                models::alias::Alias::new(user.user_id, scope, command_name.clone(), command_text);
+            alias.is_macro = is_macro;
+            alias.attachment_urls = attachment_urls;
+            alias.set_embed(embed)?;
+
+            let query_result = {
+                use schema::aliases::dsl::*;
+                insert_into(aliases).values(alias).execute(conn.deref())
+            };
+
+            match query_result {
+                Ok(_) => "Successfully added alias".to_owned(),
+                // Someone else added an alias with the same name since the check above
+                Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                    format!("An alias named `{}` already exists", command_name)
+                }
+                Err(e) => format!("Failed to add alias: {}", e),
+            }
+        }
+    };
+
+    send_without_mentions(ctx, msg, message).await
+}
+
+#[command]
+async fn edit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (scope_flag, _) = take_flags(&mut args, false)?;
+    let command_name = args.quoted().single::<String>()?;
+    let command_text = args.rest().to_string();
+    if command_text.trim().is_empty() {
+        return Err("Usage: `alias edit <name> <text>`".into());
+    }
+
+    let alias = find_alias(ctx, msg, &command_name, scope_flag).await?;
+
+    let response = match alias {
+        Some(mut a) if can_manage(ctx, msg, &a).await => {
+            if let AliasScope::Guild(guild_id) = a.scope {
+                let settings = check_guild_permission(ctx, msg, guild_id).await?;
+                check_length(&settings, &command_text)?;
+            }
+
+            let conn = util::get_conn(ctx).await;
+            let conn = conn.lock().await;
 
-        match query_result {
-            Ok(_) => "Successfully added alias".to_owned(),
-            Err(e) => format!("Failed to add alias: {}", e),
+            a.command_text = command_text;
+            a.update(conn.deref())?;
+            "Successfully edited alias"
         }
+        Some(_) => "You are not the owner of this alias or an administrator",
+        None => "Could not find alias",
     };
 
-    msg.reply(&ctx, message).await?;
+    msg.reply(&ctx, response).await?;
 
     Ok(())
 }
 
 #[command]
 async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (scope_flag, _) = take_flags(&mut args, false)?;
     let command_name = args.quoted().single::<String>()?;
 
     if !args.is_empty() {
@@ -57,37 +213,961 @@ async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
         );
     }
 
+    let alias = find_alias(ctx, msg, &command_name, scope_flag).await?;
+
+    let response = match alias {
+        Some(a) if can_manage(ctx, msg, &a).await => {
+            if let AliasScope::Guild(guild_id) = a.scope {
+                check_guild_permission(ctx, msg, guild_id).await?;
+            }
+
+            let conn = util::get_conn(ctx).await;
+            let conn = conn.lock().await;
+
+            a.delete(conn.deref())?;
+            "Successfully deleted alias"
+        }
+        Some(_) => "You are not the owner of this alias or an administrator",
+        None => "Could not find alias",
+    };
+
+    msg.reply(&ctx, response).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn list(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.map(|GuildId(id)| id);
+
+    let owner = args.current().and_then(parse_username);
+    if owner.is_some() {
+        args.advance();
+    }
+    let page = if args.is_empty() {
+        1
+    } else {
+        args.single::<i64>()?
+    };
+    if page < 1 {
+        return Err("Page must be at least 1".into());
+    }
+
+    let (total, aliases) = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let total =
+            models::alias::Alias::count_visible_to(conn.deref(), guild_id, msg.author.id.0, owner)?;
+        let aliases = models::alias::Alias::get_visible_to(
+            conn.deref(),
+            guild_id,
+            msg.author.id.0,
+            owner,
+            (page - 1) * ALIASES_PER_PAGE,
+            ALIASES_PER_PAGE,
+        )?;
+        (total, aliases)
+    };
+
+    let pages = ((total + ALIASES_PER_PAGE - 1) / ALIASES_PER_PAGE).max(1);
+    let response = if total == 0 {
+        match owner {
+            Some(owner) => format!("<@{}> has no aliases you can use here", owner),
+            None => "There are no aliases you can use here".to_string(),
+        }
+    } else if aliases.is_empty() {
+        format!("Page {} is out of range, there are {} page(s)", page, pages)
+    } else {
+        format!(
+            "Aliases (page {} of {}):\n{}",
+            page,
+            pages,
+            describe_aliases(&aliases)
+        )
+    };
+
+    send_without_mentions(ctx, msg, response).await
+}
+
+#[command]
+async fn info(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (scope_flag, _) = take_flags(&mut args, false)?;
+    let command_name = args.quoted().single::<String>()?;
+
+    let alias = find_alias(ctx, msg, &command_name, scope_flag).await?;
+    let user = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        models::user::User::get_or_create(conn.deref(), msg.author.id.0)?
+    };
+
+    let response = match alias {
+        Some(a) => format!(
+            "{} `{}`, added by <@{}> at {}, used {} time(s){}{}:\n{}",
+            describe_kind(&a),
+            a.command_name,
+            a.user_id,
+            util::format_datetime(&a.created_at.with_timezone(&user.timezone_or_utc())),
+            a.use_count,
+            a.last_used_at
+                .map(|t| format!(
+                    ", last at {}",
+                    util::format_datetime(&t.with_timezone(&user.timezone_or_utc()))
+                ))
+                .unwrap_or_default(),
+            describe_payload(&a),
+            a.command_text
+        ),
+        None => "Could not find alias".to_string(),
+    };
+    // Long alias text is cut short rather than making the reply too long to send
+    let response = util::truncate(&response, template::MAX_OUTPUT_CHARS);
+
+    send_without_mentions(ctx, msg, response).await
+}
+
+#[command]
+async fn search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let substring = args.rest().trim();
+    if substring.is_empty() {
+        return Err("Usage: `alias search <text>`".into());
+    }
+    let guild_id = msg.guild_id.map(|GuildId(id)| id);
+
+    let aliases = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        models::alias::Alias::search_containing(
+            conn.deref(),
+            guild_id,
+            msg.author.id.0,
+            substring,
+            MAX_SEARCH_RESULTS + 1,
+        )?
+    };
+
+    let response = if aliases.is_empty() {
+        format!("No aliases contain `{}`", substring)
+    } else if aliases.len() as i64 > MAX_SEARCH_RESULTS {
+        format!(
+            "More than {} aliases contain `{}`, showing the first {}:\n{}",
+            MAX_SEARCH_RESULTS,
+            substring,
+            MAX_SEARCH_RESULTS,
+            describe_aliases(&aliases[..MAX_SEARCH_RESULTS as usize])
+        )
+    } else {
+        format!(
+            "Aliases containing `{}`:\n{}",
+            substring,
+            describe_aliases(&aliases)
+        )
+    };
+
+    send_without_mentions(ctx, msg, response).await
+}
+
+#[command]
+async fn top(ctx: &Context, msg: &Message) -> CommandResult {
+    let GuildId(guild_id) = msg.guild_id.ok_or("Must be used in a server")?;
+
+    let aliases = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        models::alias::Alias::get_most_used(conn.deref(), guild_id, MAX_TOP_ALIASES)?
+    };
+
+    let response = if aliases.is_empty() {
+        "No aliases have been used in this server yet".to_string()
+    } else {
+        let lines = aliases
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                format!(
+                    "{}. `{}` by <@{}>, used {} time(s)",
+                    i + 1,
+                    a.command_name,
+                    a.user_id,
+                    a.use_count
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("Most used aliases:\n{}", lines)
+    };
+
+    send_without_mentions(ctx, msg, response).await
+}
+
+#[command]
+async fn unused(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let GuildId(guild_id) = msg.guild_id.ok_or("Must be used in a server")?;
+    let days = if args.is_empty() {
+        DEFAULT_UNUSED_DAYS
+    } else {
+        parse_days(args.rest()).ok_or("Usage: `alias unused [days]`, e.g. `alias unused 90d`")?
+    };
+    let cutoff = Utc::now() - Duration::days(days);
+
+    let aliases = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        models::alias::Alias::get_unused_since(
+            conn.deref(),
+            guild_id,
+            cutoff,
+            MAX_UNUSED_ALIASES + 1,
+        )?
+    };
+
+    let response = if aliases.is_empty() {
+        format!("Every alias has been used in the last {} day(s)", days)
+    } else if aliases.len() as i64 > MAX_UNUSED_ALIASES {
+        format!(
+            "More than {} aliases have not been used in the last {} day(s), showing the least \
+             recently used:\n{}",
+            MAX_UNUSED_ALIASES,
+            days,
+            describe_aliases(&aliases[..MAX_UNUSED_ALIASES as usize])
+        )
+    } else {
+        format!(
+            "Aliases not used in the last {} day(s):\n{}",
+            days,
+            describe_aliases(&aliases)
+        )
+    };
+
+    send_without_mentions(ctx, msg, response).await
+}
+
+#[command]
+async fn stats(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.map(|GuildId(id)| id);
+    let owner = if args.is_empty() {
+        msg.author.id.0
+    } else {
+        parse_username(args.rest().trim()).ok_or("Usage: `alias stats [@owner]`")?
+    };
+
+    let totals = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        models::alias::Alias::totals_visible_to(conn.deref(), guild_id, msg.author.id.0, owner)?
+    };
+
+    let count = totals.iter().map(|(_, count, _)| count).sum::<usize>();
+    let uses = totals.iter().map(|(_, _, uses)| uses).sum::<i64>();
+    let response = if count == 0 {
+        format!("<@{}> has no aliases you can use here", owner)
+    } else {
+        let lines = totals
+            .iter()
+            .map(|(scope, count, uses)| {
+                let scope = match scope {
+                    AliasScope::Guild(_) => "in this server",
+                    AliasScope::Personal => "personal",
+                    AliasScope::Global => "global",
+                };
+                format!("{} {}, used {} time(s)", count, scope, uses)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(
+            "<@{}> has {} alias(es) you can use here, used {} time(s) in total:\n{}",
+            owner, count, uses, lines
+        )
+    };
+
+    send_without_mentions(ctx, msg, response).await
+}
+
+#[command]
+async fn prune(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    const PRUNE_USAGE: &str =
+        "Usage: `alias prune --unused-for <days>`, e.g. `alias prune --unused-for 90d`";
+
+    let GuildId(guild_id) = msg.guild_id.ok_or("Must be used in a server")?;
+    if !util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await {
+        return Err("Only administrators can prune aliases".into());
+    }
+
+    if args.current() != Some("--unused-for") {
+        return Err(PRUNE_USAGE.into());
+    }
+    args.advance();
+    let days = parse_days(args.rest()).ok_or(PRUNE_USAGE)?;
+    let cutoff = Utc::now() - Duration::days(days);
+
+    let deleted = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        models::alias::Alias::delete_unused_since(conn.deref(), guild_id, cutoff)?
+    };
+
+    msg.reply(
+        ctx,
+        format!(
+            "Removed {} alias(es) not used in the last {} day(s)",
+            deleted, days
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The format of alias files used by `alias export` and `alias import`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+struct AliasFile {
+    #[serde(default)]
+    aliases: Vec<AliasRecord>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct AliasRecord {
+    name: String,
+    text: String,
+    /// Whether the text is a bot command to run
+    #[serde(default)]
+    command: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    embed: Option<serde_json::Value>,
+}
+
+impl AliasRecord {
+    fn from_alias(alias: models::alias::Alias) -> anyhow::Result<Self> {
+        let embed = alias
+            .embed()?
+            .map(serde_json::to_value)
+            .transpose()
+            .context("Failed to serialise alias embed")?;
+
+        Ok(Self {
+            name: alias.command_name,
+            text: alias.command_text,
+            command: alias.is_macro,
+            attachments: alias.attachment_urls,
+            embed,
+        })
+    }
+
+    /// The alias described by the record, or `None` if it is not a valid alias
+    fn into_alias(self, owner: u64, guild: u64) -> Option<models::alias::Alias> {
+        let name = self.name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return None;
+        }
+        let embed = match self.embed {
+            Some(embed) => Some(serde_json::from_value::<Embed>(embed).ok()?),
+            None => None,
+        };
+
+        let mut alias =
+            models::alias::Alias::new(owner, AliasScope::Guild(guild), name.to_string(), self.text);
+        alias.is_macro = self.command;
+        alias.attachment_urls = self.attachments;
+        alias.set_embed(embed.as_ref()).ok()?;
+
+        let is_empty = alias.command_text.trim().is_empty() && !alias.has_payload();
+        let is_invalid_macro = alias.is_macro && alias.has_payload();
+        (!is_empty && !is_invalid_macro).then_some(alias)
+    }
+}
+
+impl AliasFile {
+    /// Parse a file as JSON or TOML, going by its extension if it has a known one
+    fn parse(filename: &str, data: &[u8]) -> anyhow::Result<Self> {
+        let text = std::str::from_utf8(data).context("Alias file is not valid UTF-8")?;
+        let filename = filename.to_lowercase();
+
+        if filename.ends_with(".json") {
+            Self::parse_json(text)
+        } else if filename.ends_with(".toml") {
+            Self::parse_toml(text)
+        } else {
+            Self::parse_json(text)
+                .or_else(|_| Self::parse_toml(text))
+                .context("Alias file is neither JSON nor TOML")
+        }
+    }
+
+    fn parse_json(text: &str) -> anyhow::Result<Self> {
+        serde_json::from_str(text).context("Failed to parse alias file as JSON")
+    }
+
+    fn parse_toml(text: &str) -> anyhow::Result<Self> {
+        Figment::from(Toml::string(text))
+            .extract()
+            .context("Failed to parse alias file as TOML")
+    }
+}
+
+#[command]
+async fn export(ctx: &Context, msg: &Message) -> CommandResult {
+    let GuildId(guild_id) = msg.guild_id.ok_or("Must be used in a server")?;
+
+    let aliases = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        models::alias::Alias::get_for_guild(conn.deref(), guild_id, None, 0, i64::MAX)?
+    };
+
+    if aliases.is_empty() {
+        msg.reply(ctx, "There are no aliases in this server")
+            .await?;
+        return Ok(());
+    }
+
+    let file = AliasFile {
+        aliases: aliases
+            .into_iter()
+            .map(AliasRecord::from_alias)
+            .collect::<anyhow::Result<_>>()?,
+    };
+    let data = serde_json::to_vec_pretty(&file).context("Failed to serialise aliases")?;
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(msg);
+            m.content(format!("Exported {} alias(es)", file.aliases.len()));
+            m.add_file((data.as_slice(), "aliases.json"))
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+async fn import(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    const IMPORT_USAGE: &str =
+        "Usage: `alias import [--skip|--overwrite]` with a JSON or TOML file attached";
+
     let GuildId(guild_id) = msg.guild_id.ok_or("Must be used in a server")?;
+    if !util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await {
+        return Err("Only administrators can import aliases".into());
+    }
+
+    let overwrite = match args.rest().trim() {
+        "" | "--skip" => false,
+        "--overwrite" => true,
+        _ => return Err(IMPORT_USAGE.into()),
+    };
+    let attachment = match msg.attachments.as_slice() {
+        [attachment] => attachment,
+        _ => return Err(IMPORT_USAGE.into()),
+    };
+    if attachment.size > MAX_IMPORT_SIZE {
+        return Err(format!("Alias files can be at most {} KiB", MAX_IMPORT_SIZE / 1024).into());
+    }
 
-    let response = {
+    let data = attachment
+        .download()
+        .await
+        .context("Failed to download alias file")?;
+    let file = AliasFile::parse(&attachment.filename, &data)?;
+
+    let mut added = 0;
+    let mut overwritten = 0;
+    let mut conflicts = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut invalid = 0;
+    let mut seen = std::collections::HashSet::new();
+    {
         let conn = util::get_conn(ctx).await;
         let conn = conn.lock().await;
+        let conn = conn.deref();
+
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            for record in file.aliases {
+                let alias = match record.into_alias(msg.author.id.0, guild_id) {
+                    Some(alias) => alias,
+                    None => {
+                        invalid += 1;
+                        continue;
+                    }
+                };
+                let name = alias.command_name.clone();
+                // Later entries in the file with the same name as an earlier one are skipped
+                // rather than overwriting it
+                if !seen.insert(util::compatibility_case_fold(&name)) {
+                    duplicates.push(name);
+                    continue;
+                }
 
-        let UserId(author_id) = msg.author.id;
+                match models::alias::Alias::search(conn, &name, guild_id)? {
+                    Some(mut existing) if overwrite => {
+                        existing.replace_content(alias);
+                        existing.update(conn)?;
+                        overwritten += 1;
+                    }
+                    Some(existing) => conflicts.push(existing.command_name),
+                    None => {
+                        {
+                            use schema::aliases::dsl::*;
+                            insert_into(aliases).values(alias).execute(conn)
+                        }
+                        .with_context(|| anyhow!("Failed to add alias {}", name))?;
+                        added += 1;
+                    }
+                }
+            }
+
+            Ok(())
+        })?;
+    }
 
-        let alias = models::alias::Alias::search(conn.deref(), &command_name, guild_id)?;
+    let mut response = format!("Added {} alias(es)", added);
+    if overwrite {
+        response.push_str(&format!(", replaced {}", overwritten));
+    }
+    if invalid > 0 {
+        response.push_str(&format!(
+            ", ignored {} with an empty or multi-word name, nothing to send or an invalid embed",
+            invalid
+        ));
+    }
+    if !conflicts.is_empty() {
+        response.push_str(&format!(
+            "\nSkipped {} that already exist: {}",
+            conflicts.len(),
+            list_conflicts(&conflicts)
+        ));
+        if !overwrite {
+            response.push_str("\nUse `alias import --overwrite` to replace them");
+        }
+    }
+    if !duplicates.is_empty() {
+        response.push_str(&format!(
+            "\nSkipped {} repeated in the file, only the first of each name was imported: {}",
+            duplicates.len(),
+            list_conflicts(&duplicates)
+        ));
+    }
+
+    send_without_mentions(ctx, msg, response).await
+}
+
+/// Alias names as code, up to `MAX_LISTED_CONFLICTS` of them
+fn list_conflicts(names: &[String]) -> String {
+    let mut list = names
+        .iter()
+        .take(MAX_LISTED_CONFLICTS)
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > MAX_LISTED_CONFLICTS {
+        list.push_str(&format!(" and {} more", names.len() - MAX_LISTED_CONFLICTS));
+    }
+    list
+}
+
+#[command]
+async fn settings(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    const SETTINGS_USAGE: &str = "Usage:
+`alias settings` shows the alias settings for this server
+`alias settings permission <everyone|administrators|@role>` sets who can add, edit and remove aliases
+`alias settings max-aliases <number|none>` limits how many aliases each member can have
+`alias settings max-length <number|none>` limits how long the text of an alias can be";
+
+    let GuildId(guild_id) = msg.guild_id.ok_or("Must be used in a server")?;
+
+    let mut settings = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        GuildSettings::get_or_create(conn.deref(), guild_id)?
+    };
+
+    if args.is_empty() {
+        return send_without_mentions(ctx, msg, describe_settings(&settings)).await;
+    }
+    if !util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await {
+        return Err("Only administrators can change alias settings".into());
+    }
+
+    let setting = args.single::<String>()?;
+    let value = args.rest().trim();
+    match setting.as_str() {
+        "permission" => {
+            settings.alias_permission = match value {
+                "everyone" => AliasPermission::Everyone,
+                "administrators" | "admins" => AliasPermission::Administrators,
+                role => AliasPermission::Role(parse_role(role).ok_or(SETTINGS_USAGE)?),
+            }
+        }
+        "max-aliases" => {
+            settings.max_aliases_per_user =
+                parse_limit(value, MAX_ALIASES_PER_USER_LIMIT).ok_or(SETTINGS_USAGE)?
+        }
+        "max-length" => {
+            settings.max_alias_length =
+                parse_limit(value, template::MAX_OUTPUT_CHARS as u32).ok_or(SETTINGS_USAGE)?
+        }
+        _ => return Err(SETTINGS_USAGE.into()),
+    }
+
+    {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        settings.update(conn.deref())?;
+    }
+
+    send_without_mentions(
+        ctx,
+        msg,
+        format!("Updated alias settings\n{}", describe_settings(&settings)),
+    )
+    .await
+}
+
+/// Parse a limit between 1 and `max`, or `none` for no limit
+fn parse_limit(s: &str, max: u32) -> Option<Option<u32>> {
+    if s == "none" {
+        return Some(None);
+    }
+
+    s.parse::<u32>()
+        .ok()
+        .filter(|&n| n > 0 && n <= max)
+        .map(Some)
+}
+
+fn describe_settings(settings: &GuildSettings) -> String {
+    let describe_limit = |limit: Option<u32>| match limit {
+        Some(n) => n.to_string(),
+        None => "no limit".to_string(),
+    };
 
-         if let Some(a) = alias {
+    format!(
+        "Who can add, edit and remove aliases: {}\n\
+         Most aliases per member: {}\n\
+         Longest alias text: {}",
+        match settings.alias_permission {
+            AliasPermission::Everyone => "everyone".to_string(),
+            AliasPermission::Role(role_id) => format!("members with <@&{}>", role_id),
+            AliasPermission::Administrators => "administrators".to_string(),
+        },
+        describe_limit(settings.max_aliases_per_user),
+        describe_limit(settings.max_alias_length),
+    )
+}
+
+/// The settings of a guild, if they let the author of `msg` add, edit and remove aliases there
+async fn check_guild_permission(
+    ctx: &Context,
+    msg: &Message,
+    guild_id: u64,
+) -> Result<GuildSettings, CommandError> {
+    let settings = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        GuildSettings::get_or_create(conn.deref(), guild_id)?
+    };
+
+    let allowed = match settings.alias_permission {
+        AliasPermission::Everyone => true,
+        AliasPermission::Role(role_id) => {
+            GuildId(guild_id)
+                .member(ctx, msg.author.id)
+                .await
+                .map(|m| m.roles.contains(&RoleId(role_id)))
+                .unwrap_or(false)
+                || util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await
+        }
+        AliasPermission::Administrators => {
+            util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await
+        }
+    };
+
+    if allowed {
+        Ok(settings)
+    } else {
+        // The role is not mentioned, as errors are sent with mentions allowed
+        Err(match settings.alias_permission {
+            AliasPermission::Role(_) => {
+                "Only members with the role shown in `alias settings` \
+                 can add, edit or remove aliases in this server"
+            }
+            _ => "Only administrators can add, edit or remove aliases in this server",
+        }
+        .into())
+    }
+}
+
+fn check_length(settings: &GuildSettings, text: &str) -> Result<(), CommandError> {
+    match settings.max_alias_length {
+        Some(max) if text.chars().count() > max as usize => Err(format!(
+            "Alias text can be at most {} characters long in this server",
+            max
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Parse a number of days such as `90`, `90d` or `12w`
+fn parse_days(s: &str) -> Option<i64> {
+    let s = s.trim().to_lowercase();
+    let (number, multiplier) = if let Some(weeks) = s.strip_suffix('w') {
+        (weeks, 7)
+    } else {
+        (s.strip_suffix('d').unwrap_or(&s), 1)
+    };
+
+    number
+        .parse::<i64>()
+        .ok()
+        .filter(|&n| n > 0 && n <= 36500)
+        .map(|n| n * multiplier)
+}
+
+/// Which aliases a command applies to, chosen with `--server`, `--personal` or `--global`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ScopeFlag {
+    Server,
+    Personal,
+    Global,
+}
+
+/// Take the options before an alias name, returning the scope chosen and whether `--command`
+/// was given
+fn take_flags(args: &mut Args, allow_command: bool) -> Result<(Option<ScopeFlag>, bool), String> {
+    let mut scope = None;
+    let mut is_macro = false;
+
+    while let Some(flag) = args.current().filter(|a| a.starts_with("--")) {
+        let flag_scope = match flag {
+            "--server" => ScopeFlag::Server,
+            "--personal" => ScopeFlag::Personal,
+            "--global" => ScopeFlag::Global,
+            "--command" if allow_command => {
+                is_macro = true;
+                args.advance();
+                continue;
+            }
+            _ => return Err(format!("Unknown option `{}`", flag)),
+        };
+        if scope.replace(flag_scope).is_some() {
+            return Err("Only one of `--server`, `--personal` and `--global` can be used".into());
+        }
+        args.advance();
+    }
+
+    Ok((scope, is_macro))
+}
+
+fn scope_for(flag: ScopeFlag, msg: &Message) -> Result<AliasScope, CommandError> {
+    match flag {
+        ScopeFlag::Server => {
             let GuildId(guild_id) = msg
                 .guild_id
-                .ok_or("Must be used in the guild where the alias was added")?;
+                .ok_or("Server aliases can only be used in a server")?;
+            Ok(AliasScope::Guild(guild_id))
+        }
+        ScopeFlag::Personal => Ok(AliasScope::Personal),
+        ScopeFlag::Global => Ok(AliasScope::Global),
+    }
+}
 
-            let authorised = a.guild_id == guild_id
-                && (a.user_id == author_id
-                    || util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await);
+/// The alias named `name` in the scope chosen, or the one that would be used here if none was
+async fn find_alias(
+    ctx: &Context,
+    msg: &Message,
+    name: &str,
+    scope_flag: Option<ScopeFlag>,
+) -> Result<Option<models::alias::Alias>, CommandError> {
+    let scope = scope_flag.map(|flag| scope_for(flag, msg)).transpose()?;
 
-            if authorised {
-                a.delete(conn.deref())?;
-                "Successfully deleted alias"
-            } else {
-                "You are not the owner of this alias or an administrator"
-            }
-        } else {
-             "Could not find alias"
+    let conn = util::get_conn(ctx).await;
+    let conn = conn.lock().await;
+
+    let alias = match scope {
+        Some(scope) => {
+            models::alias::Alias::find_in_scope(conn.deref(), name, scope, msg.author.id.0)?
         }
+        None => models::alias::Alias::resolve(
+            conn.deref(),
+            name,
+            msg.guild_id.map(|GuildId(id)| id),
+            msg.author.id.0,
+        )?,
     };
+    Ok(alias)
+}
 
-    msg.reply(&ctx, response).await?;
+/// Who may change an alias: its owner if it is personal, the bot owner if it is global, and its
+/// owner or an administrator of its guild, from within that guild, otherwise
+async fn can_manage(ctx: &Context, msg: &Message, alias: &models::alias::Alias) -> bool {
+    match alias.scope {
+        AliasScope::Personal => alias.user_id == msg.author.id.0,
+        AliasScope::Global => util::user_is_bot_owner(ctx, msg.author.id.0).await,
+        AliasScope::Guild(guild_id) => {
+            msg.guild_id == Some(GuildId(guild_id))
+                && (alias.user_id == msg.author.id.0
+                    || util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await)
+        }
+    }
+}
+
+fn describe_kind(alias: &models::alias::Alias) -> String {
+    let scope = match alias.scope {
+        AliasScope::Guild(_) => "Server",
+        AliasScope::Personal => "Personal",
+        AliasScope::Global => "Global",
+    };
+    let kind = if alias.is_macro {
+        "command alias"
+    } else {
+        "alias"
+    };
+    format!("{} {}", scope, kind)
+}
+
+fn describe_payload(alias: &models::alias::Alias) -> String {
+    let mut parts = Vec::new();
+    if !alias.attachment_urls.is_empty() {
+        parts.push(format!("{} attachment(s)", alias.attachment_urls.len()));
+    }
+    if alias.embed().ok().flatten().is_some() {
+        parts.push("an embed".to_string());
+    }
+
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(", sends {}", parts.join(" and "))
+    }
+}
+
+fn describe_aliases(aliases: &[models::alias::Alias]) -> String {
+    aliases
+        .iter()
+        .map(|a| {
+            let scope = match a.scope {
+                AliasScope::Guild(_) => "",
+                AliasScope::Personal => " (personal)",
+                AliasScope::Global => " (global)",
+            };
+            format!("`{}`{} by <@{}>", a.command_name, scope, a.user_id)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reply without pinging alias owners or anyone mentioned in alias text
+async fn send_without_mentions(ctx: &Context, msg: &Message, content: String) -> CommandResult {
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(msg);
+            m.content(content);
+            m.allowed_mentions(|a| a.empty_parse())
+        })
+        .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected() -> AliasFile {
+        AliasFile {
+            aliases: vec![
+                AliasRecord {
+                    name: "hi".to_string(),
+                    text: "hello {1:world}".to_string(),
+                    command: false,
+                    attachments: Vec::new(),
+                    embed: None,
+                },
+                AliasRecord {
+                    name: "rm".to_string(),
+                    text: "remind me in 1h".to_string(),
+                    command: true,
+                    attachments: Vec::new(),
+                    embed: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn parses_json() {
+        let json = r#"{"aliases": [
+            {"name": "hi", "text": "hello {1:world}"},
+            {"name": "rm", "text": "remind me in 1h", "command": true}
+        ]}"#;
+        assert_eq!(
+            AliasFile::parse("aliases.json", json.as_bytes()).unwrap(),
+            expected()
+        );
+    }
+
+    #[test]
+    fn parses_toml() {
+        let toml = r#"
+            [[aliases]]
+            name = "hi"
+            text = "hello {1:world}"
+
+            [[aliases]]
+            name = "rm"
+            text = "remind me in 1h"
+            command = true
+        "#;
+        assert_eq!(
+            AliasFile::parse("aliases.toml", toml.as_bytes()).unwrap(),
+            expected()
+        );
+        assert_eq!(
+            AliasFile::parse("aliases.txt", toml.as_bytes()).unwrap(),
+            expected()
+        );
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let data = serde_json::to_vec(&expected()).unwrap();
+        assert_eq!(AliasFile::parse("aliases.json", &data).unwrap(), expected());
+    }
+
+    #[test]
+    fn records_need_something_to_send() {
+        let record = |text: &str, command, attachments: &[&str]| AliasRecord {
+            name: "meme".to_string(),
+            text: text.to_string(),
+            command,
+            attachments: attachments.iter().map(|s| s.to_string()).collect(),
+            embed: None,
+        };
+
+        assert!(record("", false, &["https://example.com/a.png"])
+            .into_alias(1, 2)
+            .is_some());
+        assert!(record(" ", false, &[]).into_alias(1, 2).is_none());
+        assert!(record("ping", true, &["https://example.com/a.png"])
+            .into_alias(1, 2)
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(AliasFile::parse("aliases.json", b"name = 'hi'").is_err());
+        assert!(AliasFile::parse("aliases", b"<aliases/>").is_err());
+    }
+}
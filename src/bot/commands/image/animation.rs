@@ -0,0 +1,400 @@
+use super::{check_pixels, webp_animation};
+use anyhow::{anyhow, Context, Result};
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::io::Reader;
+use image::{
+    AnimationDecoder, ColorType, Delay, ImageDecoder, ImageEncoder, ImageFormat, RgbaImage,
+};
+use photon_rs::PhotonImage;
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+/// Most frames an animated image can have
+const MAX_FRAMES: usize = 500;
+/// Quality of the lossy compression used for animated WebP images
+const WEBP_QUALITY: f32 = 90.0;
+/// Speed of the colour quantisation used for GIF images, from 1 (slowest) to 30
+///
+/// The default of 1 can take minutes for a long animation, for little visible difference.
+const GIF_SPEED: i32 = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum AnimationFormat {
+    Gif,
+    WebP,
+}
+
+pub(super) struct Frame {
+    pub image: PhotonImage,
+    /// How long the frame is shown for
+    pub delay: Duration,
+}
+
+/// When processing an image has to be done by
+///
+/// It is checked between frames, as a blocking task cannot be cancelled when its request times
+/// out and would otherwise carry on working through the frames.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(duration: Duration) -> Self {
+        Self(Instant::now() + duration)
+    }
+
+    pub fn check(self) -> Result<()> {
+        if Instant::now() >= self.0 {
+            Err(anyhow!("Processing timed out"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A still image, or the frames of an animated one
+pub(super) struct Frames {
+    pub frames: Vec<Frame>,
+    /// The format of the image if it is animated, which it is sent back in
+    pub animation: Option<AnimationFormat>,
+}
+
+impl Frames {
+    /// Decode an image, refusing it from its header if it has more than `max_pixels` pixels
+    /// between all its frames
+    pub fn decode(
+        bytes: &[u8],
+        format: ImageFormat,
+        max_pixels: u64,
+        deadline: Deadline,
+    ) -> Result<Self> {
+        match format {
+            ImageFormat::Gif => {
+                let decoder =
+                    GifDecoder::new(Cursor::new(bytes)).context("Failed to read GIF image")?;
+                let (width, height) = decoder.dimensions();
+                check_pixels(width, height, max_pixels)?;
+                let mut budget = Budget::new(max_pixels);
+                let frames = decoder
+                    .into_frames()
+                    .map(|frame| {
+                        deadline.check()?;
+                        let frame = frame.context("Failed to decode GIF frame")?;
+                        let (width, height) = frame.buffer().dimensions();
+                        budget.add_frames(width, height, 1)?;
+                        let delay = Duration::from(frame.delay());
+                        Ok((frame.into_buffer(), delay))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Self::from_rgba_frames(frames, AnimationFormat::Gif))
+            }
+            ImageFormat::WebP => {
+                let features =
+                    webp::BitstreamFeatures::new(bytes).context("Failed to read WebP image")?;
+                if features.has_animation() {
+                    let frames =
+                        webp_animation::decode(bytes, deadline, |width, height, count| {
+                            Budget::new(max_pixels).add_frames(width, height, count)
+                        })?;
+                    Ok(Self::from_rgba_frames(frames, AnimationFormat::WebP))
+                } else {
+                    check_pixels(features.width(), features.height(), max_pixels)?;
+                    let image = webp::Decoder::new(bytes)
+                        .decode()
+                        .context("Failed to load WebP image")?
+                        .to_image();
+                    Ok(Self::still(image.to_rgba8()))
+                }
+            }
+            format => {
+                let (width, height) = Reader::with_format(Cursor::new(bytes), format)
+                    .into_dimensions()
+                    .with_context(|| anyhow!("Could not read {:?} image header", format))?;
+                check_pixels(width, height, max_pixels)?;
+                let image = image::load_from_memory_with_format(bytes, format)
+                    .with_context(|| anyhow!("Could not load image with format {:?}", format))?;
+                Ok(Self::still(image.to_rgba8()))
+            }
+        }
+    }
+
+    fn still(image: RgbaImage) -> Self {
+        Self {
+            frames: vec![Frame {
+                image: to_photon(image),
+                delay: Duration::ZERO,
+            }],
+            animation: None,
+        }
+    }
+
+    /// Frames decoded from an animated image, which is treated as still if it only has one
+    fn from_rgba_frames(frames: Vec<(RgbaImage, Duration)>, format: AnimationFormat) -> Self {
+        let animation = if frames.len() > 1 { Some(format) } else { None };
+        Self {
+            frames: frames
+                .into_iter()
+                .map(|(image, delay)| Frame {
+                    image: to_photon(image),
+                    delay,
+                })
+                .collect(),
+            animation,
+        }
+    }
+
+    /// Encode the image in the format it was given in if it is animated, or as PNG otherwise,
+    /// returning the encoded image and its file extension
+    pub fn encode(self, deadline: Deadline) -> Result<(Vec<u8>, &'static str)> {
+        match self.animation {
+            None => {
+                let frame = self
+                    .frames
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("Image has no frames"))?;
+                Ok((png_encode(&frame.image)?, "png"))
+            }
+            Some(AnimationFormat::Gif) => {
+                let mut bytes = Vec::new();
+                {
+                    let mut encoder = GifEncoder::new_with_speed(&mut bytes, GIF_SPEED);
+                    encoder
+                        .set_repeat(Repeat::Infinite)
+                        .context("Failed to make GIF loop")?;
+                    for f in self.frames {
+                        deadline.check()?;
+                        encoder
+                            .encode_frame(image::Frame::from_parts(
+                                to_rgba(f.image),
+                                0,
+                                0,
+                                Delay::from_saturating_duration(f.delay),
+                            ))
+                            .context("Failed to encode GIF image")?;
+                    }
+                }
+                Ok((bytes, "gif"))
+            }
+            Some(AnimationFormat::WebP) => {
+                let frames = self
+                    .frames
+                    .into_iter()
+                    .map(|f| (to_rgba(f.image), f.delay))
+                    .collect::<Vec<_>>();
+                let bytes = webp_animation::encode(&frames, WEBP_QUALITY, deadline)?;
+                Ok((bytes, "webp"))
+            }
+        }
+    }
+}
+
+/// Keeps count of the frames and pixels decoded from an image
+struct Budget {
+    max_pixels: u64,
+    frames: usize,
+    pixels: u64,
+}
+
+impl Budget {
+    fn new(max_pixels: u64) -> Self {
+        Self {
+            max_pixels,
+            frames: 0,
+            pixels: 0,
+        }
+    }
+
+    fn add_frames(&mut self, width: u32, height: u32, count: usize) -> Result<()> {
+        self.frames = self.frames.saturating_add(count);
+        if self.frames > MAX_FRAMES {
+            return Err(anyhow!("Image has more than {} frames", MAX_FRAMES));
+        }
+
+        let pixels = (width as u64)
+            .saturating_mul(height as u64)
+            .saturating_mul(count as u64);
+        self.pixels = self.pixels.saturating_add(pixels);
+        if self.pixels > self.max_pixels {
+            return Err(anyhow!(
+                "Image has more than the limit of {} pixels between its frames",
+                self.max_pixels
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn to_photon(image: RgbaImage) -> PhotonImage {
+    let (width, height) = image.dimensions();
+    PhotonImage::new(image.into_raw(), width, height)
+}
+
+fn to_rgba(image: PhotonImage) -> RgbaImage {
+    let (width, height) = (image.get_width(), image.get_height());
+    RgbaImage::from_raw(width, height, image.get_raw_pixels())
+        .expect("PhotonImage pixels should match its size")
+}
+
+fn png_encode(image: &PhotonImage) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let encoder = image::codecs::png::PngEncoder::new(&mut bytes);
+    encoder
+        .write_image(
+            image.get_raw_pixels().as_slice(),
+            image.get_width(),
+            image.get_height(),
+            ColorType::Rgba8,
+        )
+        .context("Failed to write image using encoder")?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(format: AnimationFormat) -> Frames {
+        let frame = |colour: u8| Frame {
+            image: to_photon(RgbaImage::from_pixel(
+                4,
+                3,
+                image::Rgba([colour, 0, 0, 255]),
+            )),
+            delay: Duration::from_millis(100),
+        };
+        Frames {
+            frames: vec![frame(0), frame(255)],
+            animation: Some(format),
+        }
+    }
+
+    const MAX_PIXELS: u64 = 100_000_000;
+
+    fn deadline() -> Deadline {
+        Deadline::after(Duration::from_secs(60))
+    }
+
+    fn round_trip(format: AnimationFormat, image_format: ImageFormat) {
+        let (bytes, _) = animation(format).encode(deadline()).unwrap();
+        let decoded = Frames::decode(&bytes, image_format, MAX_PIXELS, deadline()).unwrap();
+
+        assert_eq!(decoded.animation, Some(format));
+        assert_eq!(decoded.frames.len(), 2);
+        for frame in &decoded.frames {
+            assert_eq!((frame.image.get_width(), frame.image.get_height()), (4, 3));
+            assert_eq!(frame.delay, Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn gif_round_trip() {
+        round_trip(AnimationFormat::Gif, ImageFormat::Gif);
+    }
+
+    #[test]
+    fn webp_round_trip() {
+        round_trip(AnimationFormat::WebP, ImageFormat::WebP);
+    }
+
+    #[test]
+    fn still_images_are_png() {
+        let mut image = animation(AnimationFormat::Gif);
+        image.animation = None;
+        let (bytes, extension) = image.encode(deadline()).unwrap();
+
+        assert_eq!(extension, "png");
+        let decoded = Frames::decode(&bytes, ImageFormat::Png, MAX_PIXELS, deadline()).unwrap();
+        assert_eq!(decoded.animation, None);
+        assert_eq!(decoded.frames.len(), 1);
+    }
+
+    #[test]
+    fn images_over_budget_are_refused() {
+        for format in [AnimationFormat::Gif, AnimationFormat::WebP] {
+            let (bytes, _) = animation(format).encode(deadline()).unwrap();
+            let image_format = match format {
+                AnimationFormat::Gif => ImageFormat::Gif,
+                AnimationFormat::WebP => ImageFormat::WebP,
+            };
+            // Each frame is 4x3, and there are two of them
+            assert!(Frames::decode(&bytes, image_format, 24, deadline()).is_ok());
+            assert!(Frames::decode(&bytes, image_format, 23, deadline()).is_err());
+        }
+
+        let mut image = animation(AnimationFormat::Gif);
+        image.animation = None;
+        let (bytes, _) = image.encode(deadline()).unwrap();
+        assert!(Frames::decode(&bytes, ImageFormat::Png, 12, deadline()).is_ok());
+        assert!(Frames::decode(&bytes, ImageFormat::Png, 11, deadline()).is_err());
+    }
+
+    #[test]
+    fn expired_deadline_stops_animations() {
+        let expired = Deadline::after(Duration::ZERO);
+        for (format, image_format) in [
+            (AnimationFormat::Gif, ImageFormat::Gif),
+            (AnimationFormat::WebP, ImageFormat::WebP),
+        ] {
+            assert!(animation(format).encode(expired).is_err());
+            let (bytes, _) = animation(format).encode(deadline()).unwrap();
+            assert!(Frames::decode(&bytes, image_format, MAX_PIXELS, expired).is_err());
+        }
+    }
+
+    #[test]
+    fn header_size_is_checked_before_decoding() {
+        // A PNG claiming to be 60000x60000, with no image data
+        let mut header = Vec::new();
+        header.extend_from_slice(&60000u32.to_be_bytes());
+        header.extend_from_slice(&60000u32.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &[]);
+
+        let error = Frames::decode(&png, ImageFormat::Png, MAX_PIXELS, deadline())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("60000x60000"), "{:?}", error);
+    }
+
+    fn png_chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn budget_limits_frames_and_pixels() {
+        assert!(Budget::new(MAX_PIXELS).add_frames(1, 1, MAX_FRAMES).is_ok());
+        assert!(Budget::new(MAX_PIXELS)
+            .add_frames(1, 1, MAX_FRAMES + 1)
+            .is_err());
+        assert!(Budget::new(MAX_PIXELS)
+            .add_frames(u32::MAX, u32::MAX, 1)
+            .is_err());
+
+        let mut budget = Budget::new(MAX_PIXELS);
+        assert!(budget.add_frames(10_000, 10_000, 1).is_ok());
+        assert!(budget.add_frames(10_000, 10_000, 1).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use super::MAX_IMAGE_SIZE;
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Response, Url};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tokio::time::timeout;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest wait for the response to start, or for each part of the body after that
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 5;
+
+pub(super) struct Download {
+    pub bytes: Vec<u8>,
+    pub content_type: Option<String>,
+}
+
+/// Download a file of at most `MAX_IMAGE_SIZE` bytes from a public http(s) URL
+///
+/// Redirects are followed by hand so that every URL along the way is checked.
+pub(super) async fn download(url: &Url) -> Result<Download> {
+    let mut url = url.clone();
+    for _ in 0..=MAX_REDIRECTS {
+        let client = client_for(&url).await?;
+        let response = timeout(READ_TIMEOUT, client.get(url.clone()).send())
+            .await
+            .with_context(|| anyhow!("Timed out waiting for a response from {}", url))?
+            .with_context(|| anyhow!("Failed to get response from {}", url))?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .with_context(|| anyhow!("{} redirected without a valid location", url))?;
+            url = url
+                .join(location)
+                .with_context(|| anyhow!("{} redirected to an invalid URL", url))?;
+            continue;
+        }
+
+        let response = response
+            .error_for_status()
+            .with_context(|| anyhow!("Failed to download image from {}", url))?;
+        return read_body(response).await;
+    }
+
+    Err(anyhow!(
+        "Image URL redirected more than {} times",
+        MAX_REDIRECTS
+    ))
+}
+
+/// A client that can only connect to the public addresses that the host of `url` resolves to
+///
+/// The resolved address is pinned so that the host can't resolve to somewhere else by the time
+/// the request is made.
+async fn client_for(url: &Url) -> Result<Client> {
+    match url.scheme() {
+        "http" | "https" => {}
+        scheme => {
+            return Err(anyhow!(
+                "Only http and https URLs can be downloaded, not {}",
+                scheme
+            ))
+        }
+    }
+
+    let host = url.host_str().context("URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    // IPv6 hosts are written in brackets in URLs
+    let lookup = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = lookup_host((lookup, port))
+        .await
+        .with_context(|| anyhow!("Failed to resolve {}", host))?
+        .collect::<Vec<_>>();
+    if let Some(addr) = addrs.iter().find(|a| !is_public(a.ip())) {
+        return Err(anyhow!(
+            "{} resolves to {}, which is not a public address",
+            host,
+            addr.ip()
+        ));
+    }
+    let addr = addrs
+        .first()
+        .with_context(|| anyhow!("{} did not resolve to any addresses", host))?;
+
+    Client::builder()
+        .redirect(Policy::none())
+        .no_proxy()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .resolve(host, *addr)
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Read the body of a response, giving up as soon as it is larger than `MAX_IMAGE_SIZE`
+async fn read_body(mut response: Response) -> Result<Download> {
+    if let Some(len) = response.content_length() {
+        if len > MAX_IMAGE_SIZE {
+            return Err(anyhow!("Image is too large"));
+        }
+    }
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .map(str::to_string);
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = timeout(READ_TIMEOUT, response.chunk())
+        .await
+        .context("Timed out downloading image")?
+        .context("Failed to download image")?
+    {
+        if (bytes.len() + chunk.len()) as u64 > MAX_IMAGE_SIZE {
+            return Err(anyhow!("Image is too large"));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(Download {
+        bytes,
+        content_type,
+    })
+}
+
+/// Whether an address is on the public internet, rather than this machine or a private network
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // 0.0.0.0/8 and the 100.64.0.0/10 carrier-grade NAT range
+                || a == 0
+                || (a == 100 && b & 0xc0 == 64)
+                // 198.18.0.0/15 for benchmarking and the reserved 240.0.0.0/4
+                || (a == 198 && b & 0xfe == 18)
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let segments = ip.segments();
+            // NAT64 64:ff9b::/96 and 6to4 2002::/16 addresses reach the IPv4 address they embed
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., a, b, c, d] = ip.octets();
+                return is_public(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+            }
+            if segments[0] == 0x2002 {
+                let [_, _, a, b, c, d, ..] = ip.octets();
+                return is_public(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+            }
+            let first = segments[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_public_str(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn public_addresses_are_allowed() {
+        assert!(is_public_str("1.1.1.1"));
+        assert!(is_public_str("162.159.128.233"));
+        assert!(is_public_str("2606:4700:4700::1111"));
+    }
+
+    #[test]
+    fn internal_ipv4_addresses_are_refused() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "255.255.255.255",
+            "224.0.0.1",
+            "239.255.255.250",
+            "240.0.0.1",
+            "198.18.0.1",
+            "198.19.255.255",
+        ] {
+            assert!(!is_public_str(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn internal_ipv6_addresses_are_refused() {
+        for ip in [
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "ff02::1",
+            "ff0e::1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:c0a8:101::1",
+            "2002:7f00:1::",
+        ] {
+            assert!(!is_public_str(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn embedded_public_ipv4_addresses_are_allowed() {
+        assert!(is_public_str("64:ff9b::101:101"));
+        assert!(is_public_str("2002:101:101::1"));
+        assert!(is_public_str("198.20.0.1"));
+    }
+}
@@ -1,6 +1,11 @@
+mod animation;
+mod download;
 mod filter;
 mod ifunny;
+mod webp_animation;
 
+use crate::bot::commands::image::animation::{Deadline, Frame, Frames};
+use crate::bot::commands::image::download::download;
 use crate::bot::commands::image::filter::Filter;
 use crate::bot::commands::image::ifunny::add_ifunny_watermark;
 use anyhow::{anyhow, Context, Result};
@@ -14,11 +19,13 @@ use photon_rs::monochrome::grayscale_human_corrected;
 use photon_rs::noise::add_noise_rand;
 use photon_rs::transform::{fliph, flipv, resize, SamplingFilter};
 use photon_rs::PhotonImage;
-use reqwest::{get, Url};
+use rayon::prelude::*;
+use reqwest::Url;
 use serenity::client::Context as SContext;
 use serenity::framework::standard::macros::{command, group};
 use serenity::framework::standard::{Args, CommandResult};
 use serenity::model::prelude::*;
+use serenity::prelude::TypeMapKey;
 use std::collections::VecDeque;
 use std::io::Cursor;
 use std::str::FromStr;
@@ -27,6 +34,17 @@ use tokio::time::timeout;
 
 // 500 MiB
 const MAX_IMAGE_SIZE: u64 = 500 * 1024 * 1024;
+/// How long decoding, transforming and encoding an image can take
+const PROCESSING_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many messages before the command are searched for an image to transform
+const RECENT_MESSAGE_LIMIT: u64 = 50;
+
+/// The most pixels an image can have, from the `max_image_pixels` config option
+pub struct MaxImagePixelsContainer;
+
+impl TypeMapKey for MaxImagePixelsContainer {
+    type Value = u64;
+}
 
 #[derive(Debug, Copy, Clone)]
 enum Transformation {
@@ -86,7 +104,8 @@ impl FromStr for Transformation {
 }
 
 impl Transformation {
-    pub fn apply(self, mut image: PhotonImage) -> Result<PhotonImage> {
+    /// Apply the transformation, refusing to make the image larger than `max_pixels`
+    pub fn apply(self, mut image: PhotonImage, max_pixels: u64) -> Result<PhotonImage> {
         use Transformation::*;
         match self {
             Invert => invert(&mut image),
@@ -97,7 +116,7 @@ impl Transformation {
             Frost => frosted_glass(&mut image),
             Solarise => solarize(&mut image),
             Colourise => colorize(&mut image),
-            Ifunny => image = add_ifunny_watermark(image),
+            Ifunny => image = add_ifunny_watermark(image, max_pixels)?,
             Blur(radius) => gaussian_blur(&mut image, radius),
             Contrast(c) => adjust_contrast(&mut image, c),
             Huerotate(d) => hue_rotate_hsv(&mut image, d),
@@ -108,11 +127,9 @@ impl Transformation {
                 let height = (image.get_height() as f32 * b) as u32;
                 if width == 0 || height == 0 {
                     return Err(anyhow!("Resize to 0 width or height"));
-                } else if (width * height * 4) as u64 > MAX_IMAGE_SIZE {
-                    return Err(anyhow!("Resize too large"));
-                } else {
-                    image = resize(&image, width, height, SamplingFilter::CatmullRom);
                 }
+                check_pixels(width, height, max_pixels).context("Resize too large")?;
+                image = resize(&image, width, height, SamplingFilter::CatmullRom);
             }
             Sharpen(n) => (0..n).for_each(|_| sharpen(&mut image)),
             Filter(f) => f.apply(&mut image),
@@ -131,10 +148,36 @@ struct TransformationOpt {
 }
 
 impl TransformationOpt {
-    pub fn apply_all_transformations(&self, image: PhotonImage) -> Result<PhotonImage> {
+    pub fn apply_all_transformations(
+        &self,
+        image: PhotonImage,
+        max_pixels: u64,
+    ) -> Result<PhotonImage> {
         self.transformations
             .iter()
-            .fold(Ok(image), |r, t| r.and_then(|i| t.apply(i)))
+            .fold(Ok(image), |r, t| r.and_then(|i| t.apply(i, max_pixels)))
+    }
+
+    /// Transform each frame of an image, several at once
+    ///
+    /// The pixel budget is shared evenly between the frames.
+    pub fn apply_to_frames(
+        &self,
+        frames: Vec<Frame>,
+        max_pixels: u64,
+        deadline: Deadline,
+    ) -> Result<Vec<Frame>> {
+        let max_pixels = max_pixels / frames.len().max(1) as u64;
+        frames
+            .into_par_iter()
+            .map(|frame| {
+                deadline.check()?;
+                Ok(Frame {
+                    image: self.apply_all_transformations(frame.image, max_pixels)?,
+                    delay: frame.delay,
+                })
+            })
+            .collect()
     }
 }
 
@@ -148,7 +191,45 @@ async fn transform(ctx: &SContext, msg: &Message, mut args: Args) -> CommandResu
     to_parse.push_front("transform".to_string());
     let opt: TransformationOpt = TransformationOpt::try_parse_from(&to_parse)?;
 
-    let mut url = if let Some(user_id) = opt.user {
+    let max_pixels = {
+        let data = ctx.data.read().await;
+        *data.get::<MaxImagePixelsContainer>().unwrap()
+    };
+
+    let url = find_image_url(ctx, msg, &opt).await?;
+    let (bytes, format) = download_image(url).await?;
+
+    // Decoding and encoding large animations is as slow as transforming them, so all of it is
+    // kept off the async runtime and under the time limit
+    let deadline = Deadline::after(PROCESSING_TIMEOUT);
+    let (bytes, extension) = timeout(
+        PROCESSING_TIMEOUT,
+        tokio::task::spawn_blocking(move || -> Result<_> {
+            let mut image = Frames::decode(&bytes, format, max_pixels, deadline)?;
+            image.frames = opt
+                .apply_to_frames(image.frames, max_pixels, deadline)
+                .context("Could not process image")?;
+            // Animated images are sent back in the format they came in, and still ones as PNG
+            image
+                .encode(deadline)
+                .context("Failed to encode image for reply")
+        }),
+    )
+    .await
+    .context("Processing timed out")?
+    .context("Failed to join thread")??;
+
+    respond_with_image(ctx, msg, &msg.author.name, &bytes, extension).await?;
+    Ok(())
+}
+
+/// Find the image to transform, which is the first of:
+/// - the avatar of the user given with `-u`, or the URL given with `-i`
+/// - an image attached to the command message
+/// - an image in the message being replied to
+/// - the most recent image in the channel
+async fn find_image_url(ctx: &SContext, msg: &Message, opt: &TransformationOpt) -> Result<String> {
+    if let Some(user_id) = opt.user {
         let guild = msg
             .guild(ctx)
             .await
@@ -158,29 +239,68 @@ async fn transform(ctx: &SContext, msg: &Message, mut args: Args) -> CommandResu
             .await
             .context("Could not find member in guild")?
             .user;
-        user.face()
-    } else {
-        opt.image.clone().unwrap_or_else(|| msg.author.face())
-    };
-    if url.starts_with('<') && url.ends_with('>') {
-        let mut chars = url.chars();
-        chars.next();
-        chars.next_back();
-        url = chars.collect();
+        return Ok(user.face());
     }
-    let mut image = download_image(url).await?;
 
-    image = timeout(
-        Duration::from_secs(10),
-        tokio::task::spawn_blocking(move || opt.apply_all_transformations(image)),
-    )
-    .await
-    .context("Processing timed out")?
-    .context("Failed to join thread")?
-    .context("Could not process image")?;
+    if let Some(url) = &opt.image {
+        let url = url
+            .strip_prefix('<')
+            .and_then(|u| u.strip_suffix('>'))
+            .unwrap_or(url);
+        return Ok(url.to_string());
+    }
 
-    respond_with_image(ctx, msg, &msg.author.name, image).await?;
-    Ok(())
+    if let Some(url) = message_image_url(msg) {
+        return Ok(url);
+    }
+
+    if let Some(replied) = &msg.referenced_message {
+        return message_image_url(replied)
+            .ok_or_else(|| anyhow!("The message you replied to has no image"));
+    }
+
+    let recent = msg
+        .channel_id
+        .messages(ctx, |r| r.before(msg.id).limit(RECENT_MESSAGE_LIMIT))
+        .await
+        .context("Failed to read recent messages in this channel")?;
+    // Messages are returned newest first
+    recent.iter().find_map(message_image_url).ok_or_else(|| {
+        anyhow!(
+            "Could not find an image in the last {} messages. Attach one, reply to a message with one, or use `-i <url>` or `-u <user id>`",
+            RECENT_MESSAGE_LIMIT
+        )
+    })
+}
+
+/// The URL of the first image attached to or embedded in a message
+fn message_image_url(msg: &Message) -> Option<String> {
+    let attachment = msg
+        .attachments
+        .iter()
+        .find(|a| is_image(a.content_type.as_deref(), &a.filename))
+        .map(|a| a.url.clone());
+
+    attachment.or_else(|| {
+        msg.embeds.iter().find_map(|e| {
+            let image = e.image.as_ref().map(|i| i.url.clone());
+            // Links to images are embedded with the image as the thumbnail
+            let thumbnail = e
+                .thumbnail
+                .as_ref()
+                .filter(|_| e.kind == "image")
+                .map(|t| t.url.clone());
+            image.or(thumbnail)
+        })
+    })
+}
+
+/// Whether an attachment is an image, going by its media type if Discord gave one
+fn is_image(content_type: Option<&str>, filename: &str) -> bool {
+    match content_type {
+        Some(content_type) => content_type.starts_with("image/"),
+        None => ImageFormat::from_path(filename).is_ok(),
+    }
 }
 
 #[command]
@@ -190,59 +310,40 @@ async fn getpfp(ctx: &SContext, msg: &Message) -> CommandResult {
     Ok(())
 }
 
-fn get_format(url: &str) -> Result<ImageFormat> {
-    let mut url = Url::parse(url).unwrap();
-    url.set_query(None);
-    let url = url.to_string();
-    ImageFormat::from_path(url).context("Unknown image format")
-}
-
-async fn download_image(url: String) -> Result<PhotonImage> {
-    let format =
-        get_format(&url).context("Could not determine format when attempting to download image")?;
-
-    let response = get(&url)
-        .await
-        .with_context(|| anyhow!("Failed to get response from {}", &url))?;
-
-    if let Some(len) = response.content_length() {
-        if len > MAX_IMAGE_SIZE {
-            return Err(anyhow!("Image is too large"));
-        }
+/// Work out the format of an image from its first bytes, falling back to the `Content-Type` it
+/// was served with and then the extension of the URL for formats that can't be recognised that way
+fn detect_format(bytes: &[u8], content_type: Option<&str>, url: &Url) -> Result<ImageFormat> {
+    if let Ok(format) = image::guess_format(bytes) {
+        return Ok(format);
     }
 
-    let bytes = response
-        .bytes()
-        .await
-        .context("Failed to get bytes from GET response")?;
+    content_type
+        .and_then(|t| ImageFormat::from_mime_type(t.split(';').next().unwrap_or_default().trim()))
+        .or_else(|| ImageFormat::from_path(url.path()).ok())
+        .ok_or_else(|| anyhow!("Could not recognise the image format"))
+}
 
-    let image = if format == ImageFormat::WebP {
-        webp::Decoder::new(&bytes)
-            .decode()
-            .context("Failed to load WebP image")?
-            .to_image()
-    } else {
-        image::load_from_memory_with_format(&bytes, format)
-            .with_context(|| anyhow!("Could not load image with format {:?}", format))?
-    };
+/// Download an image and work out its format, leaving it to be decoded later
+async fn download_image(url: String) -> Result<(Vec<u8>, ImageFormat)> {
+    let url = Url::parse(&url).with_context(|| anyhow!("`{}` is not a valid URL", url))?;
+
+    let download = download(&url).await?;
 
-    let raw_pixels = image.to_rgba8().to_vec();
-    Ok(PhotonImage::new(raw_pixels, image.width(), image.height()))
+    let format = detect_format(&download.bytes, download.content_type.as_deref(), &url)?;
+    Ok((download.bytes, format))
 }
 
 async fn respond_with_image(
     ctx: &SContext,
     msg: &Message,
     filename: &str,
-    image: PhotonImage,
+    bytes: &[u8],
+    extension: &str,
 ) -> Result<Message> {
-    // Always send the image in PNG format
-    let image = png_encode(image).context("Failed to encode image for reply")?;
-    let pixels = image.get_raw_pixels();
-    let filename = format!("{}.png", filename);
-    let files = vec![(pixels.as_slice(), filename.as_str())];
+    let filename = format!("{}.{}", filename, extension);
+    let files = vec![(bytes, filename.as_str())];
     msg.channel_id
-        .send_files(ctx, files.into_iter(), |m| {
+        .send_files(ctx, files, |m| {
             m.reference_message(msg);
             m.allowed_mentions(|a| a.empty_users())
         })
@@ -250,21 +351,20 @@ async fn respond_with_image(
         .context("Failed to send message")
 }
 
-fn png_encode(image: PhotonImage) -> Result<PhotonImage> {
-    let mut cursor = Cursor::new(Vec::new());
-    let encoder = image::codecs::png::PngEncoder::new(&mut cursor);
-    let pixels = image.get_raw_pixels();
-    encoder
-        .write_image(
-            pixels.as_slice(),
-            image.get_width(),
-            image.get_height(),
-            ColorType::Rgba8,
-        )
-        .context("Failed to write image using encoder")?;
-    cursor.set_position(0);
-    let image = PhotonImage::new(cursor.into_inner(), image.get_width(), image.get_height());
-    Ok(image)
+/// Check that an image of the given size would have at most `max_pixels` pixels, so that it
+/// can be refused before it is decoded or created
+fn check_pixels(width: u32, height: u32, max_pixels: u64) -> Result<()> {
+    let pixels = u64::from(width) * u64::from(height);
+    if pixels > max_pixels {
+        Err(anyhow!(
+            "Image would be {}x{}, which is more than the limit of {} pixels",
+            width,
+            height,
+            max_pixels
+        ))
+    } else {
+        Ok(())
+    }
 }
 
 fn jpeg_encode(image: PhotonImage, quality: u8) -> Result<PhotonImage> {
@@ -307,3 +407,82 @@ fn f32ratio_amount(amount: &str) -> Result<(f32, f32)> {
         Ok((a, b))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attachments_are_images_by_media_type() {
+        assert!(is_image(Some("image/png"), "file"));
+        assert!(is_image(Some("image/gif"), "file.txt"));
+        assert!(!is_image(Some("video/mp4"), "file.png"));
+        assert!(!is_image(Some("text/plain"), "file.txt"));
+    }
+
+    #[test]
+    fn attachments_without_media_type_are_images_by_extension() {
+        assert!(is_image(None, "file.png"));
+        assert!(is_image(None, "file.JPG"));
+        assert!(!is_image(None, "file.mp4"));
+        assert!(!is_image(None, "file"));
+    }
+
+    #[test]
+    fn resize_is_limited_by_pixels() {
+        let image = PhotonImage::new(vec![0; 4 * 10 * 10], 10, 10);
+
+        let resized = Transformation::Resize((2.0, 3.0))
+            .apply(image.clone(), 600)
+            .unwrap();
+        assert_eq!((resized.get_width(), resized.get_height()), (20, 30));
+        assert!(Transformation::Resize((2.0, 3.0))
+            .apply(image.clone(), 599)
+            .is_err());
+        // Would overflow the old u32 size check
+        assert!(Transformation::Resize((1e9, 1e9))
+            .apply(image, u64::MAX / 2)
+            .is_err());
+    }
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn format_is_detected_from_content_first() {
+        let format = detect_format(
+            PNG_SIGNATURE,
+            Some("image/jpeg"),
+            &url("https://example.com/image.gif"),
+        );
+        assert_eq!(format.unwrap(), ImageFormat::Png);
+    }
+
+    #[test]
+    fn format_falls_back_to_content_type() {
+        let format = detect_format(
+            b"",
+            Some("image/gif; charset=binary"),
+            &url("https://example.com/image.png?size=64"),
+        );
+        assert_eq!(format.unwrap(), ImageFormat::Gif);
+    }
+
+    #[test]
+    fn format_falls_back_to_extension() {
+        let format = detect_format(
+            b"",
+            Some("application/octet-stream"),
+            &url("https://example.com/image.tga?size=64"),
+        );
+        assert_eq!(format.unwrap(), ImageFormat::Tga);
+    }
+
+    #[test]
+    fn unknown_format_is_an_error() {
+        assert!(detect_format(b"", None, &url("https://example.com/image")).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Decoding and encoding of animated WebP images
+//!
+//! The `webp` crate only handles still images, so this uses the animation API of the libwebp it
+//! is built on, through the same version of `libwebp_sys` that it uses.
+
+use super::animation::Deadline;
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use libwebp_sys::{
+    WebPAnimDecoder, WebPAnimDecoderDelete, WebPAnimDecoderGetInfo, WebPAnimDecoderGetNext,
+    WebPAnimDecoderHasMoreFrames, WebPAnimDecoderNew, WebPAnimDecoderOptions,
+    WebPAnimDecoderOptionsInit, WebPAnimEncoder, WebPAnimEncoderAdd, WebPAnimEncoderAssemble,
+    WebPAnimEncoderDelete, WebPAnimEncoderGetError, WebPAnimEncoderNewInternal,
+    WebPAnimEncoderOptions, WebPAnimEncoderOptionsInitInternal, WebPAnimInfo, WebPConfig,
+    WebPConfigInitInternal, WebPData, WebPDataClear, WebPPicture, WebPPictureFree,
+    WebPPictureImportRGBA, WebPPictureInitInternal, WebPPreset, WEBP_CSP_MODE,
+    WEBP_ENCODER_ABI_VERSION, WEBP_MUX_ABI_VERSION,
+};
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::os::raw::c_int;
+use std::ptr;
+use std::time::Duration;
+
+/// Deletes the decoder when dropped
+struct Decoder(*mut WebPAnimDecoder);
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        unsafe { WebPAnimDecoderDelete(self.0) }
+    }
+}
+
+/// Deletes the encoder when dropped
+struct Encoder(*mut WebPAnimEncoder);
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        unsafe { WebPAnimEncoderDelete(self.0) }
+    }
+}
+
+impl Encoder {
+    fn error(&self) -> anyhow::Error {
+        let message = unsafe { CStr::from_ptr(WebPAnimEncoderGetError(self.0)) };
+        anyhow!(
+            "Failed to encode animated WebP image: {}",
+            message.to_string_lossy()
+        )
+    }
+}
+
+/// Decode every frame of an animated WebP image, along with how long each is shown for
+///
+/// `check_size` is given the canvas size and frame count before anything is decoded, so that
+/// images that are too large can be refused.
+pub(super) fn decode<F>(
+    data: &[u8],
+    deadline: Deadline,
+    check_size: F,
+) -> Result<Vec<(RgbaImage, Duration)>>
+where
+    F: FnOnce(u32, u32, usize) -> Result<()>,
+{
+    let webp_data = WebPData {
+        bytes: data.as_ptr(),
+        size: data.len(),
+    };
+
+    unsafe {
+        let mut options = MaybeUninit::<WebPAnimDecoderOptions>::uninit();
+        if WebPAnimDecoderOptionsInit(options.as_mut_ptr()) == 0 {
+            return Err(anyhow!("libwebp has an incompatible demux version"));
+        }
+        let mut options = options.assume_init();
+        options.color_mode = WEBP_CSP_MODE::MODE_RGBA;
+
+        let decoder = Decoder(WebPAnimDecoderNew(&webp_data, &options));
+        if decoder.0.is_null() {
+            return Err(anyhow!("Failed to parse animated WebP image"));
+        }
+
+        let mut info = WebPAnimInfo::default();
+        if WebPAnimDecoderGetInfo(decoder.0, &mut info) == 0 {
+            return Err(anyhow!("Failed to read animated WebP image info"));
+        }
+        let (width, height) = (info.canvas_width, info.canvas_height);
+        check_size(width, height, info.frame_count as usize)?;
+
+        let mut frames = Vec::with_capacity(info.frame_count as usize);
+        let mut previous_timestamp = 0;
+        while WebPAnimDecoderHasMoreFrames(decoder.0) != 0 {
+            deadline.check()?;
+            let mut buffer = ptr::null_mut();
+            let mut timestamp = 0;
+            if WebPAnimDecoderGetNext(decoder.0, &mut buffer, &mut timestamp) == 0 {
+                return Err(anyhow!("Failed to decode animated WebP frame"));
+            }
+
+            // The buffer is owned by the decoder and overwritten by the next frame
+            let len = width as usize * height as usize * 4;
+            let pixels = std::slice::from_raw_parts(buffer, len).to_vec();
+            let frame = RgbaImage::from_raw(width, height, pixels)
+                .ok_or_else(|| anyhow!("Animated WebP frame had the wrong size"))?;
+
+            // Timestamps are when each frame ends
+            let delay = (timestamp - previous_timestamp).max(0) as u64;
+            previous_timestamp = timestamp;
+            frames.push((frame, Duration::from_millis(delay)));
+        }
+
+        Ok(frames)
+    }
+}
+
+/// Encode frames of the same size as a looping animated WebP image
+pub(super) fn encode(
+    frames: &[(RgbaImage, Duration)],
+    quality: f32,
+    deadline: Deadline,
+) -> Result<Vec<u8>> {
+    let (width, height) = frames
+        .first()
+        .map(|(f, _)| f.dimensions())
+        .ok_or_else(|| anyhow!("Animation has no frames"))?;
+
+    unsafe {
+        let mut options = MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+        if WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), WEBP_MUX_ABI_VERSION as c_int)
+            == 0
+        {
+            return Err(anyhow!("libwebp has an incompatible mux version"));
+        }
+        let options = options.assume_init();
+
+        let encoder = Encoder(WebPAnimEncoderNewInternal(
+            width as c_int,
+            height as c_int,
+            &options,
+            WEBP_MUX_ABI_VERSION as c_int,
+        ));
+        if encoder.0.is_null() {
+            return Err(anyhow!("Failed to create animated WebP encoder"));
+        }
+
+        let mut config = MaybeUninit::<WebPConfig>::uninit();
+        if WebPConfigInitInternal(
+            config.as_mut_ptr(),
+            WebPPreset::WEBP_PRESET_DEFAULT,
+            quality,
+            WEBP_ENCODER_ABI_VERSION as c_int,
+        ) == 0
+        {
+            return Err(anyhow!("libwebp has an incompatible encoder version"));
+        }
+        let config = config.assume_init();
+
+        let mut timestamp: c_int = 0;
+        for (frame, delay) in frames {
+            deadline.check()?;
+            if frame.dimensions() != (width, height) {
+                return Err(anyhow!("Animation frames have different sizes"));
+            }
+
+            let mut picture = MaybeUninit::<WebPPicture>::uninit();
+            if WebPPictureInitInternal(picture.as_mut_ptr(), WEBP_ENCODER_ABI_VERSION as c_int) == 0
+            {
+                return Err(anyhow!("libwebp has an incompatible encoder version"));
+            }
+            let mut picture = picture.assume_init();
+            picture.use_argb = 1;
+            picture.width = width as c_int;
+            picture.height = height as c_int;
+
+            let added = WebPPictureImportRGBA(&mut picture, frame.as_ptr(), (width * 4) as c_int)
+                != 0
+                && WebPAnimEncoderAdd(encoder.0, &mut picture, timestamp, &config) != 0;
+            WebPPictureFree(&mut picture);
+            if !added {
+                return Err(encoder.error());
+            }
+
+            let delay = c_int::try_from(delay.as_millis()).unwrap_or(c_int::MAX);
+            timestamp = timestamp.saturating_add(delay);
+        }
+
+        // A final empty frame marks when the last frame ends
+        if WebPAnimEncoderAdd(encoder.0, ptr::null_mut(), timestamp, ptr::null()) == 0 {
+            return Err(encoder.error());
+        }
+
+        let mut data = WebPData::default();
+        if WebPAnimEncoderAssemble(encoder.0, &mut data) == 0 {
+            return Err(encoder.error());
+        }
+        let bytes = std::slice::from_raw_parts(data.bytes, data.size).to_vec();
+        WebPDataClear(&mut data);
+
+        Ok(bytes)
+    }
+}
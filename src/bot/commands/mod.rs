@@ -1,10 +1,14 @@
 pub mod alias;
 pub mod hyena;
 pub mod image;
+pub mod remind;
+pub mod timezone;
 
 pub use self::image::IMAGE_GROUP;
 pub use alias::ALIAS_GROUP;
 pub use hyena::HYENA_GROUP;
+pub use remind::REMIND_GROUP;
+pub use timezone::TIMEZONE_GROUP;
 
 use serenity::framework::standard::macros::{command, group};
 use serenity::framework::standard::{Args, CommandResult};
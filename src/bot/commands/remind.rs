@@ -0,0 +1,501 @@
+use crate::models::recurrence::Recurrence;
+use crate::models::reminder::{Reminder, ReminderTarget};
+use crate::models::user::User;
+use crate::time_parser::TimeParseError;
+use crate::{schema, template, time_parser, util};
+use anyhow::Context as _;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use diesel::{insert_into, RunQueryDsl};
+use serenity::framework::standard::macros::{command, group};
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use serenity::utils::{parse_role, parse_username};
+use std::ops::Deref;
+
+const REMINDERS_PER_PAGE: i64 = 10;
+/// Longest reminder text shown for each reminder in `reminders list`
+const LIST_TEXT_CHARS: usize = 100;
+/// Longest reminder text shown by `reminders show`, leaving room for the details before it
+const SHOW_TEXT_CHARS: usize = 1500;
+
+#[group]
+#[commands(remind, reminders)]
+pub struct Remind;
+
+#[command]
+async fn remind(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let target = match parse_target(ctx, msg, &args.single::<String>()?).await {
+        Ok(target) => target,
+        Err(response) => {
+            msg.reply(ctx, response).await?;
+            return Ok(());
+        }
+    };
+
+    // Reminders set in DMs are always delivered by DM
+    let by_dm = msg.guild_id.is_none() || args.current() == Some("--dm");
+    if args.current() == Some("--dm") {
+        args.advance();
+    }
+    if by_dm && matches!(target, ReminderTarget::Role(_)) {
+        return Err("Reminders for a role cannot be sent by DM".into());
+    }
+
+    let (user, accepted) = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let user = User::get_or_create(conn.deref(), msg.author.id.0)?;
+        let accepted = match target {
+            ReminderTarget::User(id) => {
+                User::get_or_create(conn.deref(), id)?.reminders_from_others
+            }
+            ReminderTarget::Creator | ReminderTarget::Role(_) => true,
+        };
+        (user, accepted)
+    };
+    if !accepted {
+        msg.channel_id
+            .send_message(ctx, |m| {
+                m.reference_message(msg);
+                m.content(format!(
+                    "{} does not accept reminders from other people",
+                    describe_recipient(target)
+                ));
+                m.allowed_mentions(|a| a.empty_parse())
+            })
+            .await?;
+        return Ok(());
+    }
+    let now = Utc::now().with_timezone(&user.timezone_or_utc());
+
+    let (reminder_time, recurrence, reminder_text) = match parse_schedule(args.rest(), &now) {
+        Ok(parsed) => parsed,
+        Err(response) => {
+            msg.reply(ctx, response).await?;
+            return Ok(());
+        }
+    };
+    let reminder_text = reminder_text.trim().to_string();
+    if reminder_text.is_empty() {
+        return Err("Reminder text must not be empty".into());
+    }
+
+    let mut reminder = Reminder::from_message(msg, reminder_time, reminder_text);
+    reminder.target = target;
+    reminder.recurrence = recurrence.clone();
+    if by_dm {
+        reminder.guild_id = None;
+    }
+
+    {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        use schema::reminders::dsl::*;
+        insert_into(reminders)
+            .values(reminder)
+            .execute(conn.deref())
+            .context("Failed to add reminder")?;
+    }
+
+    let how = if by_dm { "DM" } else { "remind" };
+    let response = match recurrence {
+        Some(r) => format!(
+            "Okay, I will {} {} at {}, repeating {}",
+            how,
+            describe_recipient(target),
+            util::format_datetime(&reminder_time),
+            r
+        ),
+        None => format!(
+            "Okay, I will {} {} at {}",
+            how,
+            describe_recipient(target),
+            util::format_datetime(&reminder_time)
+        ),
+    };
+    // Don't ping the target until the reminder is actually delivered
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(msg);
+            m.content(response);
+            m.allowed_mentions(|a| a.empty_parse())
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[sub_commands(list, show, cancel, clear, optout, optin)]
+async fn reminders(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    list_reminders(ctx, msg, args).await
+}
+
+#[command]
+async fn list(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    list_reminders(ctx, msg, args).await
+}
+
+#[command]
+async fn show(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let id = args.single::<i64>()?;
+
+    let (user, reminder) = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let user = User::get_or_create(conn.deref(), msg.author.id.0)?;
+        (user, Reminder::get(conn.deref(), id)?)
+    };
+
+    let response = match reminder {
+        Some(r) if can_manage(ctx, msg, &r).await => {
+            let time = r.reminder_time.with_timezone(&user.timezone_or_utc());
+            format!(
+                "Reminder {} for {} {} at {}{}{}:\n{}",
+                id,
+                describe_target(&r),
+                describe_destination(&r),
+                util::format_datetime(&time),
+                describe_recurrence(&r),
+                describe_state(&r),
+                util::truncate(&r.reminder_text, SHOW_TEXT_CHARS)
+            )
+        }
+        Some(_) => "You are not the owner of this reminder or an administrator".to_string(),
+        None => "Could not find reminder".to_string(),
+    };
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(msg);
+            m.content(response);
+            m.allowed_mentions(|a| a.empty_parse())
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+async fn cancel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let id = args.single::<i64>()?;
+
+    let reminder = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        Reminder::get(conn.deref(), id)?
+    };
+
+    let response = match reminder {
+        Some(r) if r.triggered => "That reminder has already been delivered",
+        Some(r) if can_manage(ctx, msg, &r).await => {
+            let conn = util::get_conn(ctx).await;
+            let conn = conn.lock().await;
+
+            r.delete(conn.deref())?;
+            "Successfully cancelled reminder"
+        }
+        Some(_) => "You are not the owner of this reminder or an administrator",
+        None => "Could not find reminder",
+    };
+
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn clear(ctx: &Context, msg: &Message) -> CommandResult {
+    let deleted = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        Reminder::delete_pending_for_user(conn.deref(), msg.author.id.0)?
+    };
+
+    msg.reply(ctx, format!("Cancelled {} pending reminder(s)", deleted))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+async fn optout(ctx: &Context, msg: &Message) -> CommandResult {
+    let cancelled = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let mut user = User::get_or_create(conn.deref(), msg.author.id.0)?;
+        user.reminders_from_others = false;
+        user.update(conn.deref())?;
+        Reminder::delete_pending_from_others(conn.deref(), msg.author.id.0)?
+    };
+
+    msg.reply(
+        ctx,
+        format!(
+            "Other people can no longer set reminders for you, cancelled {} pending reminder(s) \
+             they had set",
+            cancelled
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+async fn optin(ctx: &Context, msg: &Message) -> CommandResult {
+    {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let mut user = User::get_or_create(conn.deref(), msg.author.id.0)?;
+        user.reminders_from_others = true;
+        user.update(conn.deref())?;
+    }
+
+    msg.reply(ctx, "Other people can now set reminders for you")
+        .await?;
+
+    Ok(())
+}
+
+async fn list_reminders(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let page = if args.is_empty() {
+        1
+    } else {
+        args.single::<i64>()?
+    };
+    if page < 1 {
+        return Err("Page must be at least 1".into());
+    }
+
+    let (user, total, reminders) = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let user = User::get_or_create(conn.deref(), msg.author.id.0)?;
+        let total = Reminder::count_pending_for_user(conn.deref(), user.user_id)?;
+        let reminders = Reminder::get_pending_for_user(
+            conn.deref(),
+            user.user_id,
+            (page - 1) * REMINDERS_PER_PAGE,
+            REMINDERS_PER_PAGE,
+        )?;
+        (user, total, reminders)
+    };
+
+    let pages = ((total + REMINDERS_PER_PAGE - 1) / REMINDERS_PER_PAGE).max(1);
+    let response = if total == 0 {
+        "You have no pending reminders".to_string()
+    } else if reminders.is_empty() {
+        format!("Page {} is out of range, there are {} page(s)", page, pages)
+    } else {
+        let tz = user.timezone_or_utc();
+        let lines = reminders
+            .iter()
+            .map(|r| {
+                format!(
+                    "`{}` {}{}{}{} {}: {}",
+                    r.id().unwrap_or_default(),
+                    util::format_datetime(&r.reminder_time.with_timezone(&tz)),
+                    describe_recurrence(r),
+                    describe_state(r),
+                    match r.target {
+                        ReminderTarget::Creator => String::new(),
+                        target => format!(" for {}", describe_recipient(target)),
+                    },
+                    describe_destination(r),
+                    util::truncate(&r.reminder_text, LIST_TEXT_CHARS)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let response = format!("Pending reminders (page {} of {}):\n{}", page, pages, lines);
+        // Long schedules could still push a full page past Discord's limit
+        util::truncate(&response, template::MAX_OUTPUT_CHARS)
+    };
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(msg);
+            m.content(response);
+            m.allowed_mentions(|a| a.empty_parse())
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Parse `<time> <text>`, `every <interval> [<time>] <text>` or `cron <expression> <text>`,
+/// returning a message to reply with if the input is invalid
+fn parse_schedule<'a>(
+    input: &'a str,
+    now: &DateTime<Tz>,
+) -> Result<(DateTime<Tz>, Option<Recurrence>, &'a str), String> {
+    let first_word = input.split_whitespace().next().unwrap_or("").to_lowercase();
+    if first_word != "every" && first_word != "cron" {
+        let (time, text) =
+            time_parser::parse_time(input, now).map_err(|e| describe_time_error(&e, input))?;
+        return Ok((time, None, text));
+    }
+
+    let (recurrence, rest) = Recurrence::parse_prefix(input).map_err(|e| e.to_string())?;
+
+    // Cron expressions determine their own times, other recurrences may give a start time
+    let (start, text) = match recurrence {
+        Recurrence::Cron { .. } => (None, rest),
+        _ => match time_parser::parse_time(rest, now) {
+            Ok((time, text)) => (Some(time), text),
+            // The recurrence is directly followed by the reminder text
+            Err(TimeParseError::UnexpectedToken { position: 0, .. })
+            | Err(TimeParseError::UnexpectedEnd { .. }) => (None, rest),
+            Err(e) => return Err(describe_time_error(&e, rest)),
+        },
+    };
+
+    let first = start
+        .or_else(|| recurrence.next_after(now))
+        .and_then(|t| recurrence.align(t))
+        .ok_or_else(|| format!("`{}` never occurs", recurrence))?;
+
+    Ok((first, Some(recurrence.anchored(&first)), text))
+}
+
+fn describe_time_error(e: &TimeParseError, input: &str) -> String {
+    match e.highlight(input) {
+        Some(highlight) => format!("{}\n```\n{}\n```", e, highlight),
+        None => e.to_string(),
+    }
+}
+
+fn describe_recurrence<TZ: chrono::TimeZone>(reminder: &Reminder<TZ>) -> String {
+    reminder
+        .recurrence
+        .as_ref()
+        .map(|r| format!(", repeating {}", r))
+        .unwrap_or_default()
+}
+
+/// Parse who a reminder is for: `me`, a user mention or a role mention
+async fn parse_target(
+    ctx: &Context,
+    msg: &Message,
+    target: &str,
+) -> Result<ReminderTarget, String> {
+    if target.eq_ignore_ascii_case("me") {
+        return Ok(ReminderTarget::Creator);
+    }
+
+    let user_id = parse_username(target);
+    let role_id = parse_role(target);
+    if user_id.is_none() && role_id.is_none() {
+        return Err(format!(
+            "Unknown reminder target `{}`, expected `me`, a user or a role",
+            target
+        ));
+    }
+    if user_id == Some(msg.author.id.0) {
+        return Ok(ReminderTarget::Creator);
+    }
+
+    let guild_id = msg
+        .guild_id
+        .ok_or("Reminders for other people can only be set in a server")?;
+
+    if let Some(user_id) = user_id {
+        let member = guild_id
+            .member(ctx, user_id)
+            .await
+            .map_err(|_| "That user is not a member of this server")?;
+        if member.user.bot {
+            return Err("Bots cannot be reminded".to_string());
+        }
+        return Ok(ReminderTarget::User(user_id));
+    }
+
+    let role_id = role_id.unwrap_or_default();
+    let roles = guild_id
+        .roles(ctx)
+        .await
+        .map_err(|_| "Could not look up the roles in this server")?;
+    let role = roles
+        .get(&RoleId(role_id))
+        .filter(|_| role_id != guild_id.0)
+        .ok_or("That role is not in this server")?;
+    // Don't let reminders be used to ping roles that the author could not ping themselves
+    if !role.mentionable
+        && !util::user_is_administrator_in_guild(ctx, guild_id.0, msg.author.id.0).await
+    {
+        return Err("That role cannot be mentioned by everyone".to_string());
+    }
+
+    Ok(ReminderTarget::Role(role_id))
+}
+
+fn describe_recipient(target: ReminderTarget) -> String {
+    match target {
+        ReminderTarget::Creator => "you".to_string(),
+        ReminderTarget::User(id) => format!("<@{}>", id),
+        ReminderTarget::Role(id) => format!("<@&{}>", id),
+    }
+}
+
+fn describe_target<TZ: chrono::TimeZone>(reminder: &Reminder<TZ>) -> String {
+    match reminder.target {
+        ReminderTarget::Creator => format!("<@{}>", reminder.user_id),
+        target => format!(
+            "{} (set by <@{}>)",
+            describe_recipient(target),
+            reminder.user_id
+        ),
+    }
+}
+
+fn describe_destination<TZ: chrono::TimeZone>(reminder: &Reminder<TZ>) -> String {
+    match reminder.guild_id {
+        Some(_) => format!("in <#{}>", reminder.channel_id),
+        None => "by DM".to_string(),
+    }
+}
+
+fn describe_state<TZ: chrono::TimeZone>(reminder: &Reminder<TZ>) -> &'static str {
+    if reminder.triggered {
+        " (delivered)"
+    } else if reminder.failed {
+        " (delivery failed)"
+    } else if reminder.failed_attempts > 0 {
+        " (retrying delivery)"
+    } else {
+        ""
+    }
+}
+
+/// The owner of a reminder, the user it is for, or an administrator of the guild it was set in,
+/// may manage it
+async fn can_manage<TZ: chrono::TimeZone>(
+    ctx: &Context,
+    msg: &Message,
+    reminder: &Reminder<TZ>,
+) -> bool {
+    if reminder.user_id == msg.author.id.0
+        || reminder.target == ReminderTarget::User(msg.author.id.0)
+    {
+        return true;
+    }
+
+    // DM reminders are private to their owner
+    match reminder.guild_id {
+        Some(guild_id) if msg.guild_id == Some(GuildId(guild_id)) => {
+            util::user_is_administrator_in_guild(ctx, guild_id, msg.author.id.0).await
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,149 @@
+use crate::models::user::User;
+use crate::util;
+use chrono::Utc;
+use chrono_tz::{Tz, TZ_VARIANTS};
+use serenity::framework::standard::macros::{command, group};
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use std::ops::Deref;
+
+const MAX_SUGGESTIONS: usize = 3;
+
+#[group]
+#[commands(timezone)]
+pub struct Timezone;
+
+#[command]
+#[sub_commands(set, show, clear)]
+async fn timezone(ctx: &Context, msg: &Message) -> CommandResult {
+    show_timezone(ctx, msg).await
+}
+
+#[command]
+async fn set(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let name = args.rest().trim();
+    if name.is_empty() {
+        return Err("Usage: `timezone set <IANA timezone name>`, e.g. `Europe/London`".into());
+    }
+
+    let tz = match find_timezone(name) {
+        Some(tz) => tz,
+        None => {
+            let suggestions = suggest_timezones(name);
+            let response = if suggestions.is_empty() {
+                format!("Unknown timezone `{}`", name)
+            } else {
+                format!(
+                    "Unknown timezone `{}`, did you mean {}?",
+                    name,
+                    suggestions
+                        .iter()
+                        .map(|tz| format!("`{}`", tz.name()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            };
+            msg.reply(ctx, response).await?;
+            return Ok(());
+        }
+    };
+
+    {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let mut user = User::get_or_create(conn.deref(), msg.author.id.0)?;
+        user.timezone = Some(tz);
+        user.update(conn.deref())?;
+    }
+
+    msg.reply(
+        ctx,
+        format!(
+            "Set your timezone to `{}`, your local time is {}",
+            tz.name(),
+            util::format_datetime(&Utc::now().with_timezone(&tz))
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+async fn show(ctx: &Context, msg: &Message) -> CommandResult {
+    show_timezone(ctx, msg).await
+}
+
+#[command]
+async fn clear(ctx: &Context, msg: &Message) -> CommandResult {
+    {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        let mut user = User::get_or_create(conn.deref(), msg.author.id.0)?;
+        user.timezone = None;
+        user.update(conn.deref())?;
+    }
+
+    msg.reply(ctx, "Cleared your timezone, times will be shown in UTC")
+        .await?;
+
+    Ok(())
+}
+
+async fn show_timezone(ctx: &Context, msg: &Message) -> CommandResult {
+    let user = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        User::get_or_create(conn.deref(), msg.author.id.0)?
+    };
+
+    let response = match user.timezone {
+        Some(tz) => format!(
+            "Your timezone is `{}`, your local time is {}",
+            tz.name(),
+            util::format_datetime(&Utc::now().with_timezone(&tz))
+        ),
+        None => "You have not set a timezone, times will be shown in UTC. \
+                 Use `timezone set <IANA timezone name>` to set one"
+            .to_string(),
+    };
+
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
+fn find_timezone(name: &str) -> Option<Tz> {
+    let name = name.replace(' ', "_");
+    TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().eq_ignore_ascii_case(&name))
+        .copied()
+}
+
+/// Suggest timezones whose full name or final component (usually the city) is close to `name`
+fn suggest_timezones(name: &str) -> Vec<Tz> {
+    let name = name.replace(' ', "_").to_lowercase();
+    let threshold = (name.chars().count() / 3).max(2);
+
+    let mut candidates = TZ_VARIANTS
+        .iter()
+        .filter_map(|tz| {
+            let full = tz.name().to_lowercase();
+            let city = full.rsplit('/').next().unwrap_or(&full).to_string();
+            let distance = util::edit_distance(&name, &full).min(util::edit_distance(&name, &city));
+            (distance <= threshold).then_some((distance, *tz))
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by_key(|(distance, tz)| (*distance, tz.name()));
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, tz)| tz)
+        .collect()
+}
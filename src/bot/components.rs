@@ -0,0 +1,217 @@
+use crate::models::reminder::Reminder;
+use crate::models::user::User;
+use crate::{schema, util};
+use anyhow::{anyhow, Context as _, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use diesel::{insert_into, RunQueryDsl};
+use log::error;
+use serenity::builder::CreateComponents;
+use serenity::client::Context;
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::{
+    Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use std::ops::Deref;
+
+const REMINDER_PREFIX: &str = "reminder";
+
+#[derive(Copy, Clone, Debug)]
+enum Snooze {
+    TenMinutes,
+    OneHour,
+    Tomorrow,
+}
+
+impl Snooze {
+    const ALL: [Snooze; 3] = [Snooze::TenMinutes, Snooze::OneHour, Snooze::Tomorrow];
+
+    fn id(self) -> &'static str {
+        match self {
+            Snooze::TenMinutes => "10m",
+            Snooze::OneHour => "1h",
+            Snooze::Tomorrow => "tomorrow",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Snooze::TenMinutes => "Snooze 10m",
+            Snooze::OneHour => "Snooze 1h",
+            Snooze::Tomorrow => "Snooze until tomorrow",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|s| s.id() == id)
+    }
+
+    fn after<TZ: TimeZone>(self, now: DateTime<TZ>) -> Option<DateTime<TZ>> {
+        match self {
+            Snooze::TenMinutes => now.checked_add_signed(Duration::minutes(10)),
+            Snooze::OneHour => now.checked_add_signed(Duration::hours(1)),
+            // The same local time tomorrow, even if the clocks change overnight
+            Snooze::Tomorrow => {
+                let local = now.naive_local().checked_add_signed(Duration::days(1))?;
+                now.timezone().from_local_datetime(&local).earliest()
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum ReminderAction {
+    Snooze(Snooze),
+    Done,
+}
+
+impl ReminderAction {
+    fn custom_id(self, reminder_id: i64) -> String {
+        match self {
+            ReminderAction::Snooze(s) => {
+                format!("{}:{}:snooze:{}", REMINDER_PREFIX, reminder_id, s.id())
+            }
+            ReminderAction::Done => format!("{}:{}:done", REMINDER_PREFIX, reminder_id),
+        }
+    }
+
+    fn parse_custom_id(custom_id: &str) -> Option<(i64, Self)> {
+        let mut parts = custom_id.split(':');
+        if parts.next()? != REMINDER_PREFIX {
+            return None;
+        }
+        let reminder_id = parts.next()?.parse().ok()?;
+        let action = match (parts.next()?, parts.next()) {
+            ("snooze", Some(s)) => ReminderAction::Snooze(Snooze::from_id(s)?),
+            ("done", None) => ReminderAction::Done,
+            _ => return None,
+        };
+        Some((reminder_id, action))
+    }
+}
+
+/// The snooze and done buttons attached to a delivered reminder
+pub fn reminder_buttons(reminder_id: i64) -> CreateComponents {
+    let mut components = CreateComponents::default();
+    components.create_action_row(|row| {
+        for snooze in Snooze::ALL {
+            row.create_button(|b| {
+                b.style(ButtonStyle::Secondary)
+                    .label(snooze.label())
+                    .custom_id(ReminderAction::Snooze(snooze).custom_id(reminder_id))
+            });
+        }
+        row.create_button(|b| {
+            b.style(ButtonStyle::Success)
+                .label("Done")
+                .custom_id(ReminderAction::Done.custom_id(reminder_id))
+        })
+    });
+    components
+}
+
+pub async fn handle_interaction(ctx: &Context, interaction: Interaction) {
+    if let Interaction::MessageComponent(component) = interaction {
+        if let Some((reminder_id, action)) =
+            ReminderAction::parse_custom_id(&component.data.custom_id)
+        {
+            if let Err(e) = handle_reminder_action(ctx, &component, reminder_id, action).await {
+                error!(
+                    "Failed to handle {:?} for reminder {}: {:?}",
+                    action, reminder_id, e
+                );
+            }
+        }
+    }
+}
+
+async fn handle_reminder_action(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    reminder_id: i64,
+    action: ReminderAction,
+) -> Result<()> {
+    let reminder = {
+        let conn = util::get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        Reminder::get(conn.deref(), reminder_id)?
+    };
+
+    let mut reminder = match reminder {
+        Some(r) if r.recipient_id() == component.user.id.0 => r,
+        Some(_) => {
+            return respond_privately(
+                ctx,
+                component,
+                "Only the person being reminded can use these buttons",
+            )
+            .await
+        }
+        None => return respond_privately(ctx, component, "This reminder no longer exists").await,
+    };
+
+    let status = match action {
+        ReminderAction::Done => "Done".to_string(),
+        ReminderAction::Snooze(snooze) => {
+            let conn = util::get_conn(ctx).await;
+            let conn = conn.lock().await;
+
+            let tz = User::get_or_create(conn.deref(), component.user.id.0)?.timezone_or_utc();
+            let until = snooze
+                .after(Utc::now().with_timezone(&tz))
+                .ok_or_else(|| anyhow!("Could not compute snooze time for {:?}", snooze))?;
+
+            if reminder.recurrence.is_some() {
+                // Leave the recurring reminder on its schedule and snooze a one-off copy
+                let mut copy = Reminder::new(
+                    reminder.user_id,
+                    reminder.guild_id,
+                    reminder.channel_id,
+                    until,
+                    reminder.reminder_text.clone(),
+                );
+                copy.target = reminder.target;
+                use schema::reminders::dsl::*;
+                insert_into(reminders)
+                    .values(copy)
+                    .execute(conn.deref())
+                    .context("Failed to add snoozed reminder")?;
+            } else {
+                reminder.reminder_time = until.with_timezone(&Utc);
+                reminder.triggered = false;
+                reminder.update(conn.deref())?;
+            }
+
+            format!("Snoozed until {}", util::format_datetime(&until))
+        }
+    };
+
+    component
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| {
+                    d.content(format!("{}\n*{}*", component.message.content, status))
+                        .allowed_mentions(|a| a.empty_parse())
+                        .components(|c| c)
+                })
+        })
+        .await
+        .context("Failed to update reminder message")
+}
+
+async fn respond_privately(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    content: &str,
+) -> Result<()> {
+    component
+        .create_interaction_response(ctx, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| {
+                    d.content(content)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+        .context("Failed to respond to interaction")
+}
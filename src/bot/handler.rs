@@ -1,25 +1,40 @@
+use crate::bot::{components, scheduler};
 #[cfg(feature = "dashboard")]
 use crate::{DashboardComponentsContainer, ShardManagerContainer};
-#[cfg(feature = "dashboard")]
 use log::info;
 #[cfg(feature = "dashboard")]
 use serenity::client::bridge::gateway::ShardId;
-#[cfg(feature = "dashboard")]
 use serenity::client::Context;
-#[cfg(feature = "dashboard")]
 use serenity::model::prelude::*;
 use serenity::{async_trait, client::EventHandler};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 #[cfg(feature = "dashboard")]
 use std::time::Duration;
 use sysinfo::SystemExt;
 #[cfg(feature = "dashboard")]
 use tokio::time::{interval, Instant};
 
-pub struct Handler;
+#[derive(Default)]
+pub struct Handler {
+    reminder_scheduler_started: AtomicBool,
+}
 
 #[async_trait]
 impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!("Connected as {}", ready.user.tag());
+
+        // `ready` fires again on every reconnect, only start the scheduler once
+        if !self.reminder_scheduler_started.swap(true, Ordering::AcqRel) {
+            info!("Starting reminder scheduler");
+            tokio::spawn(scheduler::run_reminder_scheduler(ctx));
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        components::handle_interaction(&ctx, interaction).await;
+    }
+
     #[cfg(feature = "dashboard")]
     async fn cache_ready(&self, ctx: Context, _guilds: Vec<GuildId>) {
         info!("Cache is ready");
@@ -1,5 +1,9 @@
+use crate::bot::command_macro::CommandMacrosContainer;
+use crate::bot::suggestions::CommandSuggestionsContainer;
+use crate::bot::PrefixContainer;
 use crate::models::alias::Alias;
-use crate::util::get_conn;
+use crate::template::{self, TemplateContext};
+use crate::util::{self, get_conn};
 use log::{error, info};
 use serenity::framework::standard::macros::hook;
 use serenity::framework::standard::CommandResult;
@@ -42,29 +46,205 @@ pub async fn after(
 
 #[hook]
 pub async fn unrecognised_command(ctx: &Context, msg: &Message, unrecognised_command_name: &str) {
-    if let Some(GuildId(guild_id)) = msg.guild_id {
-        // Search for alias
-        let result = {
-            let conn = get_conn(ctx).await;
-            let conn = conn.lock().await;
+    let guild_id = msg.guild_id.map(|GuildId(id)| id);
 
-            Alias::search(conn.deref(), unrecognised_command_name, guild_id)
-        };
+    // Search for alias
+    let result = {
+        let conn = get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        Alias::resolve(
+            conn.deref(),
+            unrecognised_command_name,
+            guild_id,
+            msg.author.id.0,
+        )
+    };
 
+    match result {
         // If found, send the alias
-        if let Ok(Some(a)) = result {
-            let reply = msg.reply(ctx, a.command_text).await;
-            if let Err(e) = reply {
-                error!(
-                    "Failed to send alias message for '{}': {:?}",
-                    unrecognised_command_name, e
-                )
-            }
-        } else if let Err(e) = result {
+        Ok(Some(a)) => return send_alias(ctx, msg, unrecognised_command_name, a).await,
+        Ok(None) => {}
+        Err(e) => {
             error!(
                 "Failed to search database for alias '{}': {:?}",
                 unrecognised_command_name, e
-            )
+            );
+            return;
+        }
+    }
+
+    let alias_names = {
+        let conn = get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        Alias::names_visible_to(conn.deref(), guild_id, msg.author.id.0).unwrap_or_else(|e| {
+            error!("Failed to load alias names for suggestions: {:?}", e);
+            Vec::new()
+        })
+    };
+
+    let suggestions = {
+        let data = ctx.data.read().await;
+        data.get::<CommandSuggestionsContainer>().unwrap().clone()
+    };
+    let suggestions = suggestions
+        .suggest(msg.channel_id, unrecognised_command_name, &alias_names)
+        .await;
+    if suggestions.is_empty() {
+        return;
+    }
+
+    let content = format!(
+        "Unknown command `{}`, did you mean {}?",
+        unrecognised_command_name,
+        suggestions
+            .iter()
+            .map(|s| format!("`{}`", s))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let reply = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(msg);
+            m.content(content);
+            m.allowed_mentions(|a| a.empty_parse())
+        })
+        .await;
+    if let Err(e) = reply {
+        error!(
+            "Failed to send suggestions for '{}': {:?}",
+            unrecognised_command_name, e
+        )
+    }
+}
+
+/// The text after the prefix and alias name at the start of `content`
+fn alias_args<'a>(content: &'a str, prefix: &str, name: &str) -> &'a str {
+    let rest = content.trim_start();
+    let rest = rest.strip_prefix(prefix).unwrap_or(rest).trim_start();
+    match rest.get(..name.len()) {
+        Some(start) if start.to_lowercase() == name.to_lowercase() => &rest[name.len()..],
+        // The name is not where it was expected, so skip whatever the first word is
+        _ => rest
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, args)| args),
+    }
+}
+
+/// Append attachment URLs to alias text, shortening the text to leave room for them
+///
+/// Attachments are sent as links so that Discord shows them without uploading them again.
+fn with_attachment_urls(content: String, urls: &[String]) -> String {
+    if urls.is_empty() {
+        return content;
+    }
+
+    let urls = urls.join("\n");
+    let room = template::MAX_OUTPUT_CHARS.saturating_sub(urls.chars().count() + 1);
+    let content = util::truncate(&content, room);
+    let content = if content.is_empty() {
+        urls
+    } else {
+        format!("{}\n{}", content, urls)
+    };
+    util::truncate(&content, template::MAX_OUTPUT_CHARS)
+}
+
+async fn send_alias(ctx: &Context, msg: &Message, unrecognised_command_name: &str, a: Alias) {
+    {
+        let conn = get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        if let Err(e) = a.record_use(conn.deref()) {
+            error!(
+                "Failed to record use of alias '{}': {:?}",
+                a.command_name, e
+            );
         }
     }
+
+    let prefix = {
+        let data = ctx.data.read().await;
+        data.get::<PrefixContainer>().cloned().unwrap_or_default()
+    };
+    let args = alias_args(&msg.content, &prefix, unrecognised_command_name);
+    let author = msg
+        .author_nick(ctx)
+        .await
+        .unwrap_or_else(|| msg.author.name.clone());
+    let channel = msg.channel_id.mention().to_string();
+    let context = TemplateContext {
+        args,
+        author: &author,
+        channel: &channel,
+    };
+
+    if a.is_macro {
+        let content = template::render_command(&a.command_text, &context, &mut rand::thread_rng());
+        let macros = {
+            let data = ctx.data.read().await;
+            data.get::<CommandMacrosContainer>().unwrap().clone()
+        };
+        macros.run(ctx, msg, &a.command_name, &content).await;
+        return;
+    }
+
+    let content = template::render(&a.command_text, &context, &mut rand::thread_rng());
+    let content = with_attachment_urls(content, &a.attachment_urls);
+    let embed = a.embed().unwrap_or_else(|e| {
+        error!(
+            "Failed to load embed of alias '{}': {:?}",
+            a.command_name, e
+        );
+        None
+    });
+
+    let reply = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.reference_message(msg);
+            m.content(content);
+            if let Some(embed) = embed {
+                m.set_embed(embed.into());
+            }
+            m.allowed_mentions(|a| a.empty_parse().replied_user(true))
+        })
+        .await;
+    if let Err(e) = reply {
+        error!(
+            "Failed to send alias message for '{}': {:?}",
+            unrecognised_command_name, e
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alias_args_follow_prefix_and_name() {
+        assert_eq!(alias_args("!hi there you", "!", "hi"), " there you");
+        assert_eq!(alias_args("!HI there", "!", "hi"), " there");
+        assert_eq!(alias_args("!hi", "!", "hi"), "");
+    }
+
+    #[test]
+    fn alias_args_with_name_in_prefix() {
+        assert_eq!(alias_args("u!u hello", "u!", "u"), " hello");
+        assert_eq!(alias_args("!1 <@123> 1", "!", "1"), " <@123> 1");
+    }
+
+    #[test]
+    fn attachment_urls_fit_in_message() {
+        let urls = vec!["https://cdn.example/a.png".to_string(); 3];
+        let content = with_attachment_urls("x".repeat(2000), &urls);
+        assert_eq!(content.chars().count(), template::MAX_OUTPUT_CHARS);
+        assert!(content.ends_with(&urls.join("\n")));
+
+        assert_eq!(with_attachment_urls(String::new(), &urls[..1]), urls[0]);
+        assert_eq!(with_attachment_urls("hi".to_string(), &[]), "hi");
+    }
 }
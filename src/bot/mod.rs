@@ -1,17 +1,25 @@
+use crate::bot::command_macro::{CommandMacros, CommandMacrosContainer, SharedFramework};
+use crate::bot::commands::image::MaxImagePixelsContainer;
 use crate::Config;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serenity::client::bridge::gateway::ShardManager;
 use serenity::client::ClientBuilder;
 use serenity::framework::standard::CommandGroup;
 use serenity::framework::{Framework, StandardFramework};
+use serenity::http::Http;
 use serenity::prelude::TypeMapKey;
 use serenity::Client;
+use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+pub mod command_macro;
 pub mod commands;
+pub mod components;
 pub mod handler;
 pub mod hooks;
+pub mod scheduler;
+pub mod suggestions;
 
 pub struct ShardManagerContainer;
 
@@ -19,6 +27,20 @@ impl TypeMapKey for ShardManagerContainer {
     type Value = Arc<Mutex<ShardManager>>;
 }
 
+/// The users who own the bot, or are members of the team that does
+pub struct BotOwnersContainer;
+
+impl TypeMapKey for BotOwnersContainer {
+    type Value = HashSet<u64>;
+}
+
+/// The prefix that commands start with
+pub struct PrefixContainer;
+
+impl TypeMapKey for PrefixContainer {
+    type Value = String;
+}
+
 pub fn default_framework(cfg: &Config, groups: &[&'static CommandGroup]) -> impl Framework {
     let mut framework = StandardFramework::new()
         .configure(|c| c.prefix(cfg.prefix.clone()))
@@ -37,7 +59,26 @@ pub async fn default_client_builder<F>(cfg: &Config, framework: F) -> Result<Cli
 where
     F: Framework + Send + Sync + 'static,
 {
+    let framework: Arc<dyn Framework> = Arc::new(framework);
+    let macros = CommandMacros::new(Arc::clone(&framework), cfg.prefix.clone());
+
+    // Message components need the application ID, which can be looked up with the bot token
+    let application_info = Http::new_with_token(&cfg.discord_api_key)
+        .get_current_application_info()
+        .await
+        .context("Failed to get application info")?;
+    let application_id = application_info.id.0;
+    let owners = match application_info.team {
+        Some(team) => team.members.iter().map(|m| m.user.id.0).collect(),
+        None => HashSet::from([application_info.owner.id.0]),
+    };
+
     Ok(Client::builder(&cfg.discord_api_key)
-        .event_handler(handler::Handler)
-        .framework(framework))
+        .application_id(application_id)
+        .event_handler(handler::Handler::default())
+        .type_map_insert::<CommandMacrosContainer>(Arc::new(macros))
+        .type_map_insert::<BotOwnersContainer>(owners)
+        .type_map_insert::<MaxImagePixelsContainer>(cfg.max_image_pixels)
+        .type_map_insert::<PrefixContainer>(cfg.prefix.clone())
+        .framework(SharedFramework(framework)))
 }
@@ -0,0 +1,188 @@
+use crate::bot::components::reminder_buttons;
+use crate::models::reminder::{Reminder, ReminderTriggerOutcome};
+use crate::models::user::User;
+use crate::util::get_conn;
+use anyhow::Result;
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use serenity::client::Context;
+use serenity::http::{HttpError, StatusCode};
+use std::ops::Deref;
+use std::time::Duration;
+use tokio::time::interval;
+
+const REMINDER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Delivery attempts before giving up on the original channel and falling back to a DM
+const MAX_DELIVERY_ATTEMPTS: i32 = 5;
+/// Delay before the first retry, doubling after each further failure
+const RETRY_BASE_DELAY_SECS: i64 = 60;
+
+pub async fn run_reminder_scheduler(ctx: Context) {
+    if let Err(e) = catch_up_missed_reminders(&ctx).await {
+        error!("Failed to catch up on missed reminders: {:?}", e);
+    }
+
+    let mut interval = interval(REMINDER_POLL_INTERVAL);
+    // The first tick completes immediately, and the catch up has just polled
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = trigger_due_reminders(&ctx).await {
+            error!("Failed to trigger due reminders: {:?}", e);
+        }
+    }
+}
+
+/// Deliver reminders that fell due while the bot was offline
+async fn catch_up_missed_reminders(ctx: &Context) -> Result<()> {
+    let delivered = trigger_due_reminders(ctx).await?;
+    if delivered > 0 {
+        info!(
+            "Caught up on {} reminder(s) missed while offline",
+            delivered
+        );
+    }
+
+    Ok(())
+}
+
+/// Deliver every due reminder, returning how many were delivered
+async fn trigger_due_reminders(ctx: &Context) -> Result<usize> {
+    let due = {
+        let conn = get_conn(ctx).await;
+        let conn = conn.lock().await;
+
+        Reminder::get_due(conn.deref(), Utc::now())?
+    };
+
+    let mut delivered = 0;
+    for mut reminder in due {
+        // A failure here must not stop the rest of the batch from being delivered
+        match deliver(ctx, &mut reminder).await {
+            Ok(true) => delivered += 1,
+            Ok(false) => {}
+            Err(e) => error!("Failed to process reminder {:?}: {:?}", reminder.id(), e),
+        }
+    }
+
+    Ok(delivered)
+}
+
+/// Try to deliver a due reminder and record the outcome, returning whether it was delivered
+async fn deliver(ctx: &Context, reminder: &mut Reminder<Utc>) -> Result<bool> {
+    let delivered = match reminder
+        .trigger(ctx, reminder.id().map(reminder_buttons))
+        .await
+    {
+        Ok(ReminderTriggerOutcome::Success) => {
+            reschedule(ctx, reminder).await?;
+            true
+        }
+        Ok(ReminderTriggerOutcome::TooEarly | ReminderTriggerOutcome::AlreadyTriggered) => {
+            debug!("Skipping reminder {:?}", reminder);
+            false
+        }
+        Err(e) if is_transient(&e) && reminder.failed_attempts + 1 < MAX_DELIVERY_ATTEMPTS => {
+            reminder.failed_attempts += 1;
+            let delay = RETRY_BASE_DELAY_SECS << (reminder.failed_attempts - 1);
+            reminder.retry_at = Some(Utc::now() + chrono::Duration::seconds(delay));
+            warn!(
+                "Failed to trigger reminder {:?}, attempt {} of {}, retrying in {}s: {:?}",
+                reminder.id(),
+                reminder.failed_attempts,
+                MAX_DELIVERY_ATTEMPTS,
+                delay,
+                e
+            );
+
+            let conn = get_conn(ctx).await;
+            let conn = conn.lock().await;
+            reminder.update(conn.deref())?;
+            false
+        }
+        // There is nowhere else to deliver a DM reminder
+        Err(e) if reminder.guild_id.is_none() => {
+            error!("Giving up on reminder {:?}: {:?}", reminder.id(), e);
+            give_up(ctx, reminder).await?;
+            false
+        }
+        Err(e) => {
+            warn!(
+                "Failed to trigger reminder {:?}, falling back to a DM: {:?}",
+                reminder.id(),
+                e
+            );
+
+            match reminder
+                .trigger_by_dm(ctx, reminder.id().map(reminder_buttons))
+                .await
+            {
+                Ok(()) => {
+                    reschedule(ctx, reminder).await?;
+                    true
+                }
+                Err(e) => {
+                    error!("Giving up on reminder {:?}: {:?}", reminder.id(), e);
+                    give_up(ctx, reminder).await?;
+                    false
+                }
+            }
+        }
+    };
+
+    Ok(delivered)
+}
+
+/// Record a successful delivery, moving recurring reminders on to their next occurrence
+async fn reschedule(ctx: &Context, reminder: &mut Reminder<Utc>) -> Result<()> {
+    let conn = get_conn(ctx).await;
+    let conn = conn.lock().await;
+
+    reminder.failed_attempts = 0;
+    reminder.retry_at = None;
+
+    if let Some(recurrence) = &reminder.recurrence {
+        // Recurrences are evaluated in the owner's timezone so they follow DST
+        let tz = User::get(conn.deref(), reminder.user_id)?.timezone_or_utc();
+        let previous = reminder.reminder_time.with_timezone(&tz);
+        let now = Utc::now().with_timezone(&tz);
+
+        if let Some(next) = recurrence.next_after_skipping(&previous, &now) {
+            reminder.reminder_time = next.with_timezone(&Utc);
+            reminder.triggered = false;
+        } else {
+            warn!("Reminder {:?} has no further occurrences", reminder.id());
+        }
+    }
+
+    reminder.update(conn.deref())
+}
+
+/// Stop trying to deliver a reminder, leaving it for its owner to see in their list
+async fn give_up(ctx: &Context, reminder: &mut Reminder<Utc>) -> Result<()> {
+    let conn = get_conn(ctx).await;
+    let conn = conn.lock().await;
+
+    reminder.failed = true;
+    reminder.update(conn.deref())
+}
+
+/// Whether a delivery failure is likely to succeed if retried later
+///
+/// Missing channels, members and permissions are permanent, whereas rate limits, Discord
+/// outages and network errors are not.
+fn is_transient(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<serenity::Error>() {
+        Some(serenity::Error::Http(e)) => match e.as_ref() {
+            HttpError::UnsuccessfulRequest(response) => {
+                response.status_code.is_server_error()
+                    || response.status_code == StatusCode::TOO_MANY_REQUESTS
+            }
+            HttpError::Request(_) => true,
+            _ => false,
+        },
+        Some(serenity::Error::Io(_)) => true,
+        _ => false,
+    }
+}
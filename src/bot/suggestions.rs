@@ -0,0 +1,162 @@
+use crate::util::{compatibility_case_fold, edit_distance};
+use serenity::framework::standard::CommandGroup;
+use serenity::model::prelude::*;
+use serenity::prelude::TypeMapKey;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const MAX_SUGGESTIONS: usize = 3;
+/// The most edits a suggestion can be away from the name used, as a fraction of the longer name
+const MAX_EDIT_RATIO: f64 = 0.5;
+/// How long to wait before suggesting commands in the same channel again
+const SUGGESTION_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Suggests known commands and aliases when an unknown command is used
+pub struct CommandSuggestions {
+    command_names: Vec<String>,
+    last_suggested: Mutex<HashMap<ChannelId, Instant>>,
+}
+
+pub struct CommandSuggestionsContainer;
+
+impl TypeMapKey for CommandSuggestionsContainer {
+    type Value = Arc<CommandSuggestions>;
+}
+
+impl CommandSuggestions {
+    pub fn new(groups: &[&'static CommandGroup]) -> Self {
+        let mut command_names = Vec::new();
+        for group in groups {
+            add_command_names(group, &mut command_names);
+        }
+        command_names.sort();
+        command_names.dedup();
+
+        Self {
+            command_names,
+            last_suggested: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The commands and aliases closest to `name`, unless suggestions were recently made in
+    /// `channel_id`
+    pub async fn suggest(
+        &self,
+        channel_id: ChannelId,
+        name: &str,
+        alias_names: &[String],
+    ) -> Vec<String> {
+        let mut last_suggested = self.last_suggested.lock().await;
+        let now = Instant::now();
+        if let Some(last) = last_suggested.get(&channel_id) {
+            if now.duration_since(*last) < SUGGESTION_COOLDOWN {
+                return Vec::new();
+            }
+        }
+
+        let suggestions = closest_matches(name, self.command_names.iter().chain(alias_names));
+        if !suggestions.is_empty() {
+            last_suggested.insert(channel_id, now);
+            // Forget channels that are no longer cooling down so the map does not grow forever
+            last_suggested.retain(|_, last| now.duration_since(*last) < SUGGESTION_COOLDOWN);
+        }
+
+        suggestions
+    }
+}
+
+/// Names that can be the first word of a command in a group: its prefixes if it has any,
+/// otherwise the names of its commands
+fn add_command_names(group: &CommandGroup, names: &mut Vec<String>) {
+    if !group.options.prefixes.is_empty() {
+        names.extend(group.options.prefixes.iter().map(|p| p.to_string()));
+        return;
+    }
+
+    for command in group.options.commands {
+        names.extend(command.options.names.iter().map(|n| n.to_string()));
+    }
+    for sub_group in group.options.sub_groups {
+        add_command_names(sub_group, names);
+    }
+}
+
+fn closest_matches<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let name = compatibility_case_fold(name);
+
+    let mut matches = candidates
+        .filter_map(|candidate| {
+            let candidate_key = compatibility_case_fold(candidate);
+            let distance = edit_distance(&name, &candidate_key);
+            is_likely_typo(&name, &candidate_key, distance).then_some((distance, candidate))
+        })
+        .collect::<Vec<_>>();
+
+    matches.sort();
+    matches.dedup_by(|(_, a), (_, b)| a == b);
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
+}
+
+/// Whether `a` could be a mistyped `b`, given the edit distance between them
+///
+/// Short names are only a few edits away from every other short name, so the distance must be
+/// small relative to the names' lengths and less than the length of the shorter one.
+fn is_likely_typo(a: &str, b: &str, distance: usize) -> bool {
+    let (a_len, b_len) = (a.chars().count(), b.chars().count());
+    distance < a_len.min(b_len) && distance as f64 <= a_len.max(b_len) as f64 * MAX_EDIT_RATIO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(name: &str, candidates: &[&str]) -> Vec<String> {
+        let candidates = candidates.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        closest_matches(name, candidates.iter())
+    }
+
+    #[test]
+    fn suggests_typos() {
+        assert_eq!(
+            matches("remnid", &["remind", "reminders", "ping"]),
+            ["remind"]
+        );
+        assert_eq!(matches("hepl", &["help", "hyena"]), ["help"]);
+        assert_eq!(matches("TIMEZONE", &["timezone"]), ["timezone"]);
+    }
+
+    #[test]
+    fn closest_first() {
+        assert_eq!(
+            matches("remind", &["reminders", "remindr", "remind"]),
+            ["remind", "remindr", "reminders"]
+        );
+    }
+
+    #[test]
+    fn short_words_do_not_match_everything() {
+        assert!(matches("hi", &["ok", "ping", "tz", "help"]).is_empty());
+        assert!(matches("ok", &["hi", "no", "oof"]).is_empty());
+        assert!(matches("a", &["b", "ab"]).is_empty());
+        assert_eq!(matches("pnig", &["ping", "hi", "help"]), ["ping"]);
+    }
+
+    #[test]
+    fn unrelated_names_are_not_suggested() {
+        assert!(matches("banana", &["remind", "timezone", "alias"]).is_empty());
+    }
+
+    #[test]
+    fn limited_and_deduplicated() {
+        assert_eq!(
+            matches("cat", &["cats", "bat", "cat", "cat", "hat", "mat"]),
+            ["cat", "bat", "cats"]
+        );
+    }
+}
@@ -10,6 +10,14 @@ pub struct Config {
     pub discord_api_key: String,
     pub prefix: String,
     pub database_url: String,
+    /// The most pixels an image can have when it is decoded or transformed
+    #[serde(default = "default_max_image_pixels")]
+    pub max_image_pixels: u64,
+}
+
+fn default_max_image_pixels() -> u64 {
+    // 500 MiB of RGBA pixels
+    500 * 1024 * 1024 / 4
 }
 
 impl TryDefault for Config {
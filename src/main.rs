@@ -1,7 +1,10 @@
 #[macro_use]
 extern crate diesel;
 
-use crate::bot::commands::{ALIAS_GROUP, GENERAL_GROUP, HYENA_GROUP, IMAGE_GROUP};
+use crate::bot::commands::{
+    ALIAS_GROUP, GENERAL_GROUP, HYENA_GROUP, IMAGE_GROUP, REMIND_GROUP, TIMEZONE_GROUP,
+};
+use crate::bot::suggestions::{CommandSuggestions, CommandSuggestionsContainer};
 #[cfg(feature = "dashboard")]
 use crate::bot::ShardManagerContainer;
 use crate::config::Config;
@@ -21,6 +24,8 @@ mod dashboard;
 mod database;
 mod models;
 mod schema;
+mod template;
+mod time_parser;
 mod util;
 
 #[tokio::main]
@@ -41,7 +46,14 @@ async fn main() -> Result<()> {
     debug!("Creating config");
     let cfg = Config::try_default()?;
 
-    let groups = [&GENERAL_GROUP, &HYENA_GROUP, &IMAGE_GROUP, &ALIAS_GROUP];
+    let groups = [
+        &GENERAL_GROUP,
+        &HYENA_GROUP,
+        &IMAGE_GROUP,
+        &ALIAS_GROUP,
+        &REMIND_GROUP,
+        &TIMEZONE_GROUP,
+    ];
 
     debug!("Creating framework");
     let framework = bot::default_framework(&cfg, &groups);
@@ -58,7 +70,11 @@ async fn main() -> Result<()> {
         builder = builder.type_map_insert::<DashboardComponentsContainer>(dashboard_components);
     }
 
-    let pg_connection = Arc::new(Mutex::new(establish_connection(&cfg)?));
+    builder = builder
+        .type_map_insert::<CommandSuggestionsContainer>(Arc::new(CommandSuggestions::new(&groups)));
+
+    let pg_connection = establish_connection(&cfg)?;
+    let pg_connection = Arc::new(Mutex::new(pg_connection));
     builder = builder.type_map_insert::<PgConnectionContainer>(pg_connection);
 
     let mut client = builder.await.context("Failed to build client")?;
@@ -2,32 +2,123 @@ use super::DB;
 use crate::schema::aliases;
 use crate::util::compatibility_case_fold;
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::expression::BoxableExpression;
+use diesel::sql_types::Bool;
 use diesel::{
-    Connection, ExpressionMethods, Insertable, OptionalExtension, PgTextExpressionMethods,
-    QueryDsl, Queryable, RunQueryDsl,
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, OptionalExtension,
+    PgSortExpressionMethods, PgTextExpressionMethods, QueryDsl, Queryable, RunQueryDsl,
 };
+use serenity::model::channel::Embed;
 use std::fmt::Debug;
 
+/// Where an alias can be used
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AliasScope {
+    /// By anyone in one guild
+    Guild(u64),
+    /// By its owner, in any guild or DM
+    Personal,
+    /// By anyone, anywhere
+    Global,
+}
+
+impl AliasScope {
+    fn name(self) -> &'static str {
+        match self {
+            AliasScope::Guild(_) => "guild",
+            AliasScope::Personal => "personal",
+            AliasScope::Global => "global",
+        }
+    }
+
+    fn guild_id(self) -> Option<u64> {
+        match self {
+            AliasScope::Guild(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// When aliases with the same name can be used in the same place, the one whose scope has
+    /// the lowest precedence wins
+    fn precedence(self) -> u8 {
+        match self {
+            AliasScope::Personal => 0,
+            AliasScope::Guild(_) => 1,
+            AliasScope::Global => 2,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Alias {
     alias_id: Option<i64>,
     pub user_id: u64,
-    pub guild_id: u64,
+    pub scope: AliasScope,
     pub command_name: String,
     pub command_text: String,
+    pub created_at: DateTime<Utc>,
+    /// Whether the text is a bot command to run rather than a message to send
+    pub is_macro: bool,
+    pub use_count: i64,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Files sent along with the text, linked by URL
+    pub attachment_urls: Vec<String>,
+    /// An embed sent along with the text, serialised as JSON
+    embed_json: Option<String>,
 }
 
 impl Alias {
-    pub fn new(user_id: u64, guild_id: u64, command_name: String, command_text: String) -> Self {
+    pub fn new(
+        user_id: u64,
+        scope: AliasScope,
+        command_name: String,
+        command_text: String,
+    ) -> Self {
         Self {
             alias_id: None,
             user_id,
-            guild_id,
+            scope,
             command_name,
             command_text,
+            created_at: Utc::now(),
+            is_macro: false,
+            use_count: 0,
+            last_used_at: None,
+            attachment_urls: Vec::new(),
+            embed_json: None,
         }
     }
 
+    pub fn embed(&self) -> Result<Option<Embed>> {
+        self.embed_json
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .context("Failed to parse alias embed")
+    }
+
+    pub fn set_embed(&mut self, embed: Option<&Embed>) -> Result<()> {
+        self.embed_json = embed
+            .map(serde_json::to_string)
+            .transpose()
+            .context("Failed to serialise alias embed")?;
+        Ok(())
+    }
+
+    /// Replace what the alias sends with what `other` sends
+    pub fn replace_content(&mut self, other: Alias) {
+        self.command_text = other.command_text;
+        self.is_macro = other.is_macro;
+        self.attachment_urls = other.attachment_urls;
+        self.embed_json = other.embed_json;
+    }
+
+    /// Whether the alias sends anything besides its text
+    pub fn has_payload(&self) -> bool {
+        !self.attachment_urls.is_empty() || self.embed_json.is_some()
+    }
+
     pub fn search<C>(conn: &C, search_term: &str, id: u64) -> Result<Option<Alias>>
     where
         C: Connection<Backend = DB>,
@@ -38,12 +129,336 @@ impl Alias {
 
         aliases
             .filter(guild_id.eq(id))
-            .filter(command_name.ilike(&search_term))
+            .filter(command_key.eq(&search_term))
+            .first(conn)
+            .optional()
+            .with_context(|| anyhow!("Failed to find alias from search term {}", &search_term))
+    }
+
+    /// The alias `user` gets by using `search_term` in `guild`, or in a DM if there is no guild
+    ///
+    /// Personal aliases take precedence over guild aliases, which take precedence over global
+    /// ones.
+    pub fn resolve<C>(
+        conn: &C,
+        search_term: &str,
+        guild: Option<u64>,
+        user: u64,
+    ) -> Result<Option<Alias>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+        let search_term = compatibility_case_fold(search_term);
+
+        let candidates = aliases
+            .filter(visible_to(guild, user))
+            .filter(command_key.eq(&search_term))
+            .load::<Alias>(conn)
+            .with_context(|| {
+                anyhow!("Failed to resolve alias from search term {}", &search_term)
+            })?;
+
+        Ok(candidates.into_iter().min_by_key(|a| a.scope.precedence()))
+    }
+
+    /// The alias named `search_term` in `scope`, which for personal aliases is owned by `user`
+    pub fn find_in_scope<C>(
+        conn: &C,
+        search_term: &str,
+        alias_scope: AliasScope,
+        user: u64,
+    ) -> Result<Option<Alias>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+        let search_term = compatibility_case_fold(search_term);
+
+        let mut query = aliases
+            .filter(scope.eq(alias_scope.name()))
+            .filter(command_key.eq(&search_term))
+            .into_boxed();
+        match alias_scope {
+            AliasScope::Guild(guild) => query = query.filter(guild_id.eq(guild as i64)),
+            AliasScope::Personal => query = query.filter(user_id.eq(user as i64)),
+            AliasScope::Global => {}
+        }
+
+        query
             .first(conn)
             .optional()
             .with_context(|| anyhow!("Failed to find alias from search term {}", &search_term))
     }
 
+    /// The names of the aliases `user` can use in `guild`, or in a DM if there is no guild
+    pub fn names_visible_to<C>(conn: &C, guild: Option<u64>, user: u64) -> Result<Vec<String>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        aliases
+            .filter(visible_to(guild, user))
+            .select(command_name)
+            .load(conn)
+            .with_context(|| anyhow!("Failed to load alias names for user {}", user))
+    }
+
+    /// Aliases in a guild ordered by name, optionally only those owned by `owner`
+    pub fn get_for_guild<C>(
+        conn: &C,
+        guild: u64,
+        owner: Option<u64>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Alias>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        let mut query = aliases.filter(guild_id.eq(guild as i64)).into_boxed();
+        if let Some(owner) = owner {
+            query = query.filter(user_id.eq(owner as i64));
+        }
+
+        query
+            .order((command_name.asc(), alias_id.asc()))
+            .offset(offset)
+            .limit(limit)
+            .load(conn)
+            .with_context(|| anyhow!("Failed to load aliases for guild {}", guild))
+    }
+
+    /// Aliases `user` can use in `guild` ordered by name, optionally only those owned by `owner`
+    pub fn get_visible_to<C>(
+        conn: &C,
+        guild: Option<u64>,
+        user: u64,
+        owner: Option<u64>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Alias>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        let mut query = aliases.filter(visible_to(guild, user)).into_boxed();
+        if let Some(owner) = owner {
+            query = query.filter(user_id.eq(owner as i64));
+        }
+
+        query
+            .order((command_name.asc(), alias_id.asc()))
+            .offset(offset)
+            .limit(limit)
+            .load(conn)
+            .with_context(|| anyhow!("Failed to load aliases for user {}", user))
+    }
+
+    pub fn count_visible_to<C>(
+        conn: &C,
+        guild: Option<u64>,
+        user: u64,
+        owner: Option<u64>,
+    ) -> Result<i64>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        let mut query = aliases.filter(visible_to(guild, user)).into_boxed();
+        if let Some(owner) = owner {
+            query = query.filter(user_id.eq(owner as i64));
+        }
+
+        query
+            .count()
+            .get_result(conn)
+            .with_context(|| anyhow!("Failed to count aliases for user {}", user))
+    }
+
+    /// Aliases in a guild whose name or text contains `substring`, ignoring case
+    /// Aliases `user` can use in `guild` whose name or text contains `substring`, ordered by name
+    pub fn search_containing<C>(
+        conn: &C,
+        guild: Option<u64>,
+        user: u64,
+        substring: &str,
+        limit: i64,
+    ) -> Result<Vec<Alias>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        let pattern = format!("%{}%", escape_like(substring));
+
+        aliases
+            .filter(visible_to(guild, user))
+            .filter(
+                command_name
+                    .ilike(&pattern)
+                    .or(command_text.ilike(&pattern)),
+            )
+            .order((command_name.asc(), alias_id.asc()))
+            .limit(limit)
+            .load(conn)
+            .with_context(|| anyhow!("Failed to search aliases for {}", substring))
+    }
+
+    pub fn update<C>(&self, conn: &C) -> Result<()>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        if let Some(id) = self.alias_id {
+            diesel::update(aliases.find(id))
+                .set((
+                    command_text.eq(&self.command_text),
+                    is_macro.eq(self.is_macro),
+                    attachment_urls.eq(&self.attachment_urls),
+                    embed_json.eq(&self.embed_json),
+                ))
+                .execute(conn)
+                .map(|_| ())
+                .with_context(|| anyhow!("Failed to update alias with ID {}", id))
+        } else {
+            Err(anyhow!("Alias to update had no ID"))
+        }
+    }
+
+    /// Count a use of the alias
+    pub fn record_use<C>(&self, conn: &C) -> Result<()>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        if let Some(id) = self.alias_id {
+            diesel::update(aliases.find(id))
+                .set((use_count.eq(use_count + 1), last_used_at.eq(Utc::now())))
+                .execute(conn)
+                .map(|_| ())
+                .with_context(|| anyhow!("Failed to record use of alias with ID {}", id))
+        } else {
+            Err(anyhow!("Alias to record use of had no ID"))
+        }
+    }
+
+    pub fn get_most_used<C>(conn: &C, guild: u64, limit: i64) -> Result<Vec<Alias>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        aliases
+            .filter(guild_id.eq(guild as i64))
+            .filter(use_count.gt(0))
+            .order((use_count.desc(), command_name.asc()))
+            .limit(limit)
+            .load(conn)
+            .with_context(|| anyhow!("Failed to load most used aliases for guild {}", guild))
+    }
+
+    /// Aliases in a guild that have not been used since `cutoff`, least recently used first
+    ///
+    /// Aliases that have never been used only count if they were added before `cutoff`.
+    pub fn get_unused_since<C>(
+        conn: &C,
+        guild: u64,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Alias>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        aliases
+            .filter(guild_id.eq(guild as i64))
+            .filter(
+                last_used_at
+                    .lt(cutoff)
+                    .or(last_used_at.is_null().and(created_at.lt(cutoff))),
+            )
+            .order((last_used_at.asc().nulls_first(), created_at.asc()))
+            .limit(limit)
+            .load(conn)
+            .with_context(|| anyhow!("Failed to load unused aliases for guild {}", guild))
+    }
+
+    pub fn delete_unused_since<C>(conn: &C, guild: u64, cutoff: DateTime<Utc>) -> Result<usize>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        diesel::delete(
+            aliases.filter(guild_id.eq(guild as i64)).filter(
+                last_used_at
+                    .lt(cutoff)
+                    .or(last_used_at.is_null().and(created_at.lt(cutoff))),
+            ),
+        )
+        .execute(conn)
+        .with_context(|| anyhow!("Failed to delete unused aliases for guild {}", guild))
+    }
+
+    /// The number of aliases `owner` has in a guild, and how many times they have been used
+    pub fn totals_for_user<C>(conn: &C, guild: u64, owner: u64) -> Result<(usize, i64)>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        let counts = aliases
+            .filter(guild_id.eq(guild as i64))
+            .filter(user_id.eq(owner as i64))
+            .select(use_count)
+            .load::<i64>(conn)
+            .with_context(|| anyhow!("Failed to load alias totals for user {}", owner))?;
+
+        Ok((counts.len(), counts.iter().sum()))
+    }
+
+    /// How many aliases `owner` has that `user` can use in `guild` and how many times they have
+    /// been used, for each scope in the order they are used in
+    pub fn totals_visible_to<C>(
+        conn: &C,
+        guild: Option<u64>,
+        user: u64,
+        owner: u64,
+    ) -> Result<Vec<(AliasScope, usize, i64)>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::aliases::dsl::*;
+
+        let owned = aliases
+            .filter(visible_to(guild, user))
+            .filter(user_id.eq(owner as i64))
+            .load::<Alias>(conn)
+            .with_context(|| anyhow!("Failed to load alias totals for user {}", owner))?;
+
+        let mut totals: Vec<(AliasScope, usize, i64)> = Vec::new();
+        for alias in owned {
+            match totals.iter_mut().find(|(s, _, _)| *s == alias.scope) {
+                Some((_, count, uses)) => {
+                    *count += 1;
+                    *uses += alias.use_count;
+                }
+                None => totals.push((alias.scope, 1, alias.use_count)),
+            }
+        }
+        totals.sort_by_key(|(s, _, _)| s.precedence());
+
+        Ok(totals)
+    }
+
     pub fn delete<C>(self, conn: &C) -> Result<()>
     where
         C: Connection<Backend = DB>,
@@ -62,19 +477,59 @@ impl Alias {
 }
 
 impl Queryable<aliases::SqlType, DB> for Alias {
-    type Row = (i64, i64, i64, String, String);
+    type Row = (
+        i64,
+        i64,
+        Option<i64>,
+        String,
+        String,
+        DateTime<Utc>,
+        bool,
+        String,
+        i64,
+        Option<DateTime<Utc>>,
+        Vec<String>,
+        Option<String>,
+        String,
+    );
 
     fn build(row: Self::Row) -> Self {
-        let (alias_id, user_id, guild_id, command_name, command_text) = row;
+        // The key is always derived from the name, so is not kept
+        let (
+            alias_id,
+            user_id,
+            guild_id,
+            command_name,
+            command_text,
+            created_at,
+            is_macro,
+            _,
+            use_count,
+            last_used_at,
+            attachment_urls,
+            embed_json,
+            scope,
+        ) = row;
         let user_id = user_id as u64;
-        let guild_id = guild_id as u64;
+        // The database only allows guild aliases to have a guild
+        let scope = match (guild_id, scope.as_str()) {
+            (Some(guild_id), _) => AliasScope::Guild(guild_id as u64),
+            (None, "global") => AliasScope::Global,
+            (None, _) => AliasScope::Personal,
+        };
 
         Alias {
             alias_id: Some(alias_id),
             user_id,
-            guild_id,
+            scope,
             command_name,
             command_text,
+            created_at,
+            is_macro,
+            use_count,
+            last_used_at,
+            attachment_urls,
+            embed_json,
         }
     }
 }
@@ -82,17 +537,30 @@ impl Queryable<aliases::SqlType, DB> for Alias {
 impl Insertable<aliases::table> for Alias {
     type Values = <(
         diesel::dsl::Eq<aliases::user_id, i64>,
-        diesel::dsl::Eq<aliases::guild_id, i64>,
+        diesel::dsl::Eq<aliases::guild_id, Option<i64>>,
         diesel::dsl::Eq<aliases::command_name, String>,
         diesel::dsl::Eq<aliases::command_text, String>,
+        diesel::dsl::Eq<aliases::created_at, DateTime<Utc>>,
+        diesel::dsl::Eq<aliases::is_macro, bool>,
+        diesel::dsl::Eq<aliases::command_key, String>,
+        diesel::dsl::Eq<aliases::attachment_urls, Vec<String>>,
+        diesel::dsl::Eq<aliases::embed_json, Option<String>>,
+        diesel::dsl::Eq<aliases::scope, &'static str>,
     ) as Insertable<aliases::table>>::Values;
 
     fn values(self) -> Self::Values {
+        let key = compatibility_case_fold(&self.command_name);
         (
             aliases::user_id.eq(self.user_id as i64),
-            aliases::guild_id.eq(self.guild_id as i64),
+            aliases::guild_id.eq(self.scope.guild_id().map(|id| id as i64)),
             aliases::command_name.eq(self.command_name),
             aliases::command_text.eq(self.command_text),
+            aliases::created_at.eq(self.created_at),
+            aliases::is_macro.eq(self.is_macro),
+            aliases::command_key.eq(key),
+            aliases::attachment_urls.eq(self.attachment_urls),
+            aliases::embed_json.eq(self.embed_json),
+            aliases::scope.eq(self.scope.name()),
         )
             .values()
     }
@@ -101,18 +569,55 @@ impl Insertable<aliases::table> for Alias {
 impl<'a> Insertable<aliases::table> for &'a Alias {
     type Values = <(
         diesel::dsl::Eq<aliases::user_id, i64>,
-        diesel::dsl::Eq<aliases::guild_id, i64>,
+        diesel::dsl::Eq<aliases::guild_id, Option<i64>>,
         diesel::dsl::Eq<aliases::command_name, &'a String>,
         diesel::dsl::Eq<aliases::command_text, &'a String>,
+        diesel::dsl::Eq<aliases::created_at, &'a DateTime<Utc>>,
+        diesel::dsl::Eq<aliases::is_macro, bool>,
+        diesel::dsl::Eq<aliases::command_key, String>,
+        diesel::dsl::Eq<aliases::attachment_urls, &'a Vec<String>>,
+        diesel::dsl::Eq<aliases::embed_json, &'a Option<String>>,
+        diesel::dsl::Eq<aliases::scope, &'static str>,
     ) as Insertable<aliases::table>>::Values;
 
     fn values(self) -> Self::Values {
         (
             aliases::user_id.eq(self.user_id as i64),
-            aliases::guild_id.eq(self.guild_id as i64),
+            aliases::guild_id.eq(self.scope.guild_id().map(|id| id as i64)),
             aliases::command_name.eq(&self.command_name),
             aliases::command_text.eq(&self.command_text),
+            aliases::created_at.eq(&self.created_at),
+            aliases::is_macro.eq(self.is_macro),
+            aliases::command_key.eq(compatibility_case_fold(&self.command_name)),
+            aliases::attachment_urls.eq(&self.attachment_urls),
+            aliases::embed_json.eq(&self.embed_json),
+            aliases::scope.eq(self.scope.name()),
         )
             .values()
     }
 }
+
+/// Matches the aliases `user` can use in `guild`, or in a DM if there is no guild
+fn visible_to(
+    guild: Option<u64>,
+    user: u64,
+) -> Box<dyn BoxableExpression<aliases::table, DB, SqlType = Bool>> {
+    use crate::schema::aliases::dsl::*;
+
+    // Comparing with a NULL guild matches nothing, so DMs only see personal and global aliases
+    Box::new(
+        scope
+            .eq(AliasScope::Global.name())
+            .or(scope
+                .eq(AliasScope::Personal.name())
+                .and(user_id.eq(user as i64)))
+            .or(guild_id.eq(guild.map(|g| g as i64))),
+    )
+}
+
+/// Escape the wildcards used by `LIKE` so that `s` only matches itself
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
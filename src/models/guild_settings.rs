@@ -0,0 +1,148 @@
+use super::DB;
+use crate::schema::guild_settings;
+use anyhow::{anyhow, Context, Result};
+use diesel::{
+    insert_into, Connection, ExpressionMethods, Insertable, QueryDsl, Queryable, RunQueryDsl,
+};
+use std::fmt::Debug;
+
+/// Who may add, edit and remove aliases in a guild
+///
+/// Administrators always may, and members can only edit and remove their own aliases.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AliasPermission {
+    Everyone,
+    Role(u64),
+    Administrators,
+}
+
+impl AliasPermission {
+    fn name(self) -> &'static str {
+        match self {
+            AliasPermission::Everyone => "everyone",
+            AliasPermission::Role(_) => "role",
+            AliasPermission::Administrators => "administrators",
+        }
+    }
+
+    fn role_id(self) -> Option<u64> {
+        match self {
+            AliasPermission::Role(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GuildSettings {
+    pub guild_id: u64,
+    pub alias_permission: AliasPermission,
+    /// How many aliases each member may own in the guild
+    pub max_aliases_per_user: Option<u32>,
+    /// How many characters the text of an alias may have
+    pub max_alias_length: Option<u32>,
+}
+
+impl GuildSettings {
+    pub fn new(id: u64) -> Self {
+        Self {
+            guild_id: id,
+            alias_permission: AliasPermission::Everyone,
+            max_aliases_per_user: None,
+            max_alias_length: None,
+        }
+    }
+
+    pub fn get<C>(conn: &C, id: u64) -> Result<Self>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::guild_settings::dsl::*;
+        guild_settings
+            .find(id as i64)
+            .get_result::<GuildSettings>(conn)
+            .with_context(|| anyhow!("Could not find settings for guild {}", id))
+    }
+
+    pub fn create<C>(conn: &C, id: u64) -> Result<Self>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::guild_settings::dsl::*;
+        let settings = GuildSettings::new(id);
+        insert_into(guild_settings)
+            .values(settings)
+            .execute(conn)
+            .with_context(|| anyhow!("Failed to create settings for guild {}", id))?;
+        Ok(settings)
+    }
+
+    pub fn update<C>(&self, conn: &C) -> Result<()>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::guild_settings::dsl::*;
+        diesel::update(guild_settings.find(self.guild_id as i64))
+            .set((
+                alias_permission.eq(self.alias_permission.name()),
+                alias_role_id.eq(self.alias_permission.role_id().map(|id| id as i64)),
+                max_aliases_per_user.eq(self.max_aliases_per_user.map(|n| n as i32)),
+                max_alias_length.eq(self.max_alias_length.map(|n| n as i32)),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .with_context(|| anyhow!("Failed to update settings for guild {}", self.guild_id))
+    }
+
+    pub fn get_or_create<C>(conn: &C, id: u64) -> Result<Self>
+    where
+        C: Connection<Backend = DB>,
+    {
+        Self::get(conn, id)
+            .or_else(|_| Self::create(conn, id))
+            .with_context(|| anyhow!("Failed to get or create settings for guild {}", id))
+    }
+}
+
+impl Queryable<guild_settings::SqlType, DB> for GuildSettings {
+    type Row = (i64, String, Option<i64>, Option<i32>, Option<i32>);
+
+    fn build(row: Self::Row) -> Self {
+        let (guild_id, alias_permission, alias_role_id, max_aliases_per_user, max_alias_length) =
+            row;
+        // The database only allows a role to be set along with the role permission
+        let alias_permission = match (alias_permission.as_str(), alias_role_id) {
+            (_, Some(role_id)) => AliasPermission::Role(role_id as u64),
+            ("administrators", None) => AliasPermission::Administrators,
+            _ => AliasPermission::Everyone,
+        };
+
+        GuildSettings {
+            guild_id: guild_id as u64,
+            alias_permission,
+            max_aliases_per_user: max_aliases_per_user.map(|n| n as u32),
+            max_alias_length: max_alias_length.map(|n| n as u32),
+        }
+    }
+}
+
+impl Insertable<guild_settings::table> for GuildSettings {
+    type Values = <(
+        diesel::dsl::Eq<guild_settings::guild_id, i64>,
+        diesel::dsl::Eq<guild_settings::alias_permission, &'static str>,
+        diesel::dsl::Eq<guild_settings::alias_role_id, Option<i64>>,
+        diesel::dsl::Eq<guild_settings::max_aliases_per_user, Option<i32>>,
+        diesel::dsl::Eq<guild_settings::max_alias_length, Option<i32>>,
+    ) as Insertable<guild_settings::table>>::Values;
+
+    fn values(self) -> Self::Values {
+        (
+            guild_settings::guild_id.eq(self.guild_id as i64),
+            guild_settings::alias_permission.eq(self.alias_permission.name()),
+            guild_settings::alias_role_id.eq(self.alias_permission.role_id().map(|id| id as i64)),
+            guild_settings::max_aliases_per_user.eq(self.max_aliases_per_user.map(|n| n as i32)),
+            guild_settings::max_alias_length.eq(self.max_alias_length.map(|n| n as i32)),
+        )
+            .values()
+    }
+}
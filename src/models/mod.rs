@@ -1,4 +1,6 @@
 pub mod alias;
+pub mod guild_settings;
+pub mod recurrence;
 pub mod reminder;
 pub mod user;
 
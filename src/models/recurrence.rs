@@ -0,0 +1,510 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Weekday,
+};
+use cron::Schedule;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How often a reminder repeats
+///
+/// Stored in the database using the same syntax users type, e.g. `every 2 hours`,
+/// `every weekday at 09:00` or `cron 0 9 * * 1-5`.
+#[derive(Clone, Debug)]
+pub enum Recurrence {
+    Minutes(u32),
+    Hours(u32),
+    /// Repeats at the same local time every N days, so is unaffected by DST changes
+    Days {
+        days: u32,
+        at: Option<NaiveTime>,
+    },
+    /// Repeats at the same local time every Monday to Friday
+    Weekdays {
+        at: Option<NaiveTime>,
+    },
+    /// A standard five field cron expression, evaluated in the owner's timezone
+    Cron {
+        expression: String,
+        schedule: Box<Schedule>,
+    },
+}
+
+impl Recurrence {
+    /// Parse a recurrence from the start of `input`, returning it along with the unparsed remainder
+    pub fn parse_prefix(input: &str) -> Result<(Self, &str)> {
+        let (keyword, rest) = split_token(input).context("Expected `every` or `cron`")?;
+
+        match keyword.to_lowercase().as_ref() {
+            "every" => Self::parse_every(rest),
+            "cron" => Self::parse_cron(rest),
+            _ => Err(anyhow!("Expected `every` or `cron`, got `{}`", keyword)),
+        }
+    }
+
+    fn parse_every(input: &str) -> Result<(Self, &str)> {
+        let (token, rest) = split_token(input)
+            .context("Expected an interval such as `2 hours`, `day` or `weekday`")?;
+        let token = token.to_lowercase();
+
+        if token == "weekday" || token == "weekdays" {
+            return Ok((Recurrence::Weekdays { at: None }, rest));
+        }
+
+        // `every 3 days`, `every day` or `every 3d`
+        let (amount, unit, rest) = if token.chars().all(|c| c.is_ascii_digit()) {
+            let (unit, rest) = split_token(rest)
+                .with_context(|| anyhow!("Expected a unit of time after `{}`", token))?;
+            (token.as_str(), unit.to_lowercase(), rest)
+        } else {
+            let digits = token
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(token.len());
+            let amount = if digits == 0 { "1" } else { &token[..digits] };
+            (amount, token[digits..].to_string(), rest)
+        };
+
+        let amount = amount
+            .parse::<u32>()
+            .ok()
+            .filter(|&n| n > 0)
+            .with_context(|| anyhow!("`{}` is not a valid number of repeats", amount))?;
+
+        let recurrence = match unit.as_ref() {
+            "m" | "min" | "mins" | "minute" | "minutes" => Recurrence::Minutes(amount),
+            "h" | "hr" | "hrs" | "hour" | "hours" => Recurrence::Hours(amount),
+            "d" | "day" | "days" => Recurrence::Days {
+                days: amount,
+                at: None,
+            },
+            "w" | "wk" | "wks" | "week" | "weeks" => Recurrence::Days {
+                days: amount
+                    .checked_mul(7)
+                    .context("Recurrence interval is too long")?,
+                at: None,
+            },
+            _ => {
+                return Err(anyhow!(
+                    "Unknown unit `{}`, expected minutes, hours, days or weeks",
+                    unit
+                ))
+            }
+        };
+
+        Ok((recurrence, rest))
+    }
+
+    fn parse_cron(input: &str) -> Result<(Self, &str)> {
+        let mut rest = input;
+        let mut fields = Vec::with_capacity(5);
+        for _ in 0..5 {
+            let (field, r) = split_token(rest).context(
+                "Expected a cron expression with five fields: minute hour day month weekday",
+            )?;
+            fields.push(field);
+            rest = r;
+        }
+
+        let expression = fields.join(" ");
+        let days_of_week = translate_days_of_week(fields[4])
+            .with_context(|| anyhow!("Invalid day of week field `{}`", fields[4]))?;
+        // The cron crate expects a leading seconds field
+        let schedule = Schedule::from_str(&format!(
+            "0 {} {} {} {} {}",
+            fields[0], fields[1], fields[2], fields[3], days_of_week
+        ))
+        .map_err(|e| anyhow!("Invalid cron expression `{}`: {}", expression, e))?;
+
+        Ok((
+            Recurrence::Cron {
+                expression,
+                schedule: Box::new(schedule),
+            },
+            rest,
+        ))
+    }
+
+    /// The first occurrence strictly after `previous`, in the same timezone
+    pub fn next_after<TZ>(&self, previous: &DateTime<TZ>) -> Option<DateTime<TZ>>
+    where
+        TZ: TimeZone,
+    {
+        match self {
+            Recurrence::Minutes(n) => previous
+                .clone()
+                .checked_add_signed(Duration::minutes(i64::from(*n))),
+            Recurrence::Hours(n) => previous
+                .clone()
+                .checked_add_signed(Duration::hours(i64::from(*n))),
+            Recurrence::Days { days, at } => {
+                let local = previous.naive_local();
+                let date = local
+                    .date()
+                    .checked_add_signed(Duration::days(i64::from(*days)))?;
+                resolve_local(
+                    &previous.timezone(),
+                    date.and_time(at.unwrap_or(local.time())),
+                )
+            }
+            Recurrence::Weekdays { at } => {
+                let local = previous.naive_local();
+                let date = next_weekday(local.date())?;
+                resolve_local(
+                    &previous.timezone(),
+                    date.and_time(at.unwrap_or(local.time())),
+                )
+            }
+            Recurrence::Cron { schedule, .. } => schedule.after(previous).next(),
+        }
+    }
+
+    /// The first occurrence strictly after `now`, skipping any that were missed
+    pub fn next_after_skipping<TZ>(
+        &self,
+        previous: &DateTime<TZ>,
+        now: &DateTime<TZ>,
+    ) -> Option<DateTime<TZ>>
+    where
+        TZ: TimeZone,
+    {
+        let mut next = self.next_after(previous)?;
+        while next <= *now {
+            next = self.next_after(&next)?;
+        }
+        Some(next)
+    }
+
+    /// Move `start` forward to the first time that fits the recurrence, if it does not already
+    pub fn align<TZ>(&self, start: DateTime<TZ>) -> Option<DateTime<TZ>>
+    where
+        TZ: TimeZone,
+    {
+        match self {
+            Recurrence::Weekdays { .. } if is_weekend(start.weekday()) => self.next_after(&start),
+            Recurrence::Cron { schedule, .. } => {
+                schedule.after(&(start - Duration::seconds(1))).next()
+            }
+            _ => Some(start),
+        }
+    }
+
+    /// Fix the local time of day that daily and weekday recurrences repeat at to that of `first`
+    ///
+    /// Without this, an occurrence moved by a DST gap would move every later occurrence too.
+    pub fn anchored<TZ>(self, first: &DateTime<TZ>) -> Self
+    where
+        TZ: TimeZone,
+    {
+        let time = first.naive_local().time();
+        let at = NaiveTime::from_hms_opt(time.hour(), time.minute(), 0);
+        match self {
+            Recurrence::Days { days, .. } => Recurrence::Days { days, at },
+            Recurrence::Weekdays { .. } => Recurrence::Weekdays { at },
+            r => r,
+        }
+    }
+}
+
+impl Display for Recurrence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn every(f: &mut Formatter<'_>, n: u32, unit: &str) -> std::fmt::Result {
+            if n == 1 {
+                write!(f, "every {}", unit)
+            } else {
+                write!(f, "every {} {}s", n, unit)
+            }
+        }
+
+        match self {
+            Recurrence::Minutes(n) => every(f, *n, "minute"),
+            Recurrence::Hours(n) => every(f, *n, "hour"),
+            Recurrence::Days { days, .. } if days % 7 == 0 => every(f, days / 7, "week"),
+            Recurrence::Days { days, .. } => every(f, *days, "day"),
+            Recurrence::Weekdays { .. } => write!(f, "every weekday"),
+            Recurrence::Cron { expression, .. } => write!(f, "cron {}", expression),
+        }?;
+
+        match self {
+            Recurrence::Days { at: Some(at), .. } | Recurrence::Weekdays { at: Some(at) } => {
+                write!(f, " at {}", at.format("%H:%M"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (recurrence, rest) = Self::parse_prefix(s)?;
+
+        // The time of day that daily and weekday recurrences are anchored to
+        let at = match split_token(rest) {
+            None => return Ok(recurrence),
+            Some((keyword, time)) if keyword.eq_ignore_ascii_case("at") => {
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .with_context(|| anyhow!("Could not parse `{}` as HH:MM", time))?
+            }
+            Some(_) => return Err(anyhow!("Unexpected text `{}` after recurrence", rest)),
+        };
+
+        match recurrence {
+            Recurrence::Days { days, .. } => Ok(Recurrence::Days { days, at: Some(at) }),
+            Recurrence::Weekdays { .. } => Ok(Recurrence::Weekdays { at: Some(at) }),
+            _ => Err(anyhow!(
+                "Only daily and weekday recurrences can have a time of day"
+            )),
+        }
+    }
+}
+
+/// Translate a standard cron day of week field, where 0 and 7 are Sunday, to weekday names
+///
+/// The cron crate numbers days from 1 for Sunday, so `1-5` would otherwise mean Sunday to
+/// Thursday rather than Monday to Friday.
+fn translate_days_of_week(field: &str) -> Option<String> {
+    const NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+    if !field.contains(|c: char| c.is_ascii_digit()) {
+        return Some(field.to_string());
+    }
+
+    let mut days = Vec::new();
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().ok().filter(|&s| s > 0)?),
+            None => (item, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (0, 6),
+            Some((start, end)) => (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?),
+            None => {
+                let day = range.parse::<usize>().ok()?;
+                (day, if step > 1 { 6 } else { day })
+            }
+        };
+        if start > end || end > 7 {
+            return None;
+        }
+        days.extend((start..=end).step_by(step).map(|d| NAMES[d % 7]));
+    }
+
+    days.dedup();
+    Some(days.join(","))
+}
+
+fn is_weekend(weekday: Weekday) -> bool {
+    matches!(weekday, Weekday::Sat | Weekday::Sun)
+}
+
+fn next_weekday(mut date: NaiveDate) -> Option<NaiveDate> {
+    loop {
+        date = date.checked_add_signed(Duration::days(1))?;
+        if !is_weekend(date.weekday()) {
+            return Some(date);
+        }
+    }
+}
+
+fn split_token(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+    let end = s.find(char::is_whitespace).unwrap_or(s.len());
+    Some((&s[..end], s[end..].trim_start()))
+}
+
+/// Convert a local time to `tz`, moving forward past any gap caused by clocks going forward
+fn resolve_local<TZ>(tz: &TZ, mut local: NaiveDateTime) -> Option<DateTime<TZ>>
+where
+    TZ: TimeZone,
+{
+    // DST gaps are at most a couple of hours
+    for _ in 0..4 {
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(t) => return Some(t),
+            LocalResult::Ambiguous(earliest, _) => return Some(earliest),
+            LocalResult::None => local = local.checked_add_signed(Duration::hours(1))?,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use chrono_tz::Europe::London;
+    use chrono_tz::Tz;
+
+    fn london(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Tz> {
+        London.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn recurrence(s: &str) -> Recurrence {
+        s.parse()
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", s, e))
+    }
+
+    #[test]
+    fn daily_across_spring_forward() {
+        // Clocks go forward from 01:00 to 02:00 on 2026-03-29
+        let r = recurrence("every day at 01:30");
+        let skipped = r.next_after(&london(2026, 3, 28, 1, 30)).unwrap();
+        assert_eq!(skipped.with_timezone(&Utc), utc(2026, 3, 29, 1, 30));
+        assert_eq!(skipped.naive_local().time().hour(), 2);
+
+        // The day after the gap is back at 01:30
+        let next = r.next_after(&skipped).unwrap();
+        assert_eq!(next, london(2026, 3, 30, 1, 30));
+        assert_eq!(next.with_timezone(&Utc), utc(2026, 3, 30, 0, 30));
+    }
+
+    #[test]
+    fn daily_keeps_local_time_across_dst() {
+        let r = recurrence("every day");
+        let next = r.next_after(&london(2026, 3, 28, 9, 0)).unwrap();
+        assert_eq!(next, london(2026, 3, 29, 9, 0));
+        assert_eq!(next.with_timezone(&Utc), utc(2026, 3, 29, 8, 0));
+    }
+
+    #[test]
+    fn daily_across_fall_back() {
+        // Clocks go back from 02:00 to 01:00 on 2026-10-25, so 01:30 happens twice
+        let r = recurrence("every day at 01:30");
+        let overlap = r.next_after(&london(2026, 10, 24, 1, 30)).unwrap();
+        assert_eq!(overlap.with_timezone(&Utc), utc(2026, 10, 25, 0, 30));
+
+        // The second 01:30 is not used as another occurrence
+        let next = r.next_after(&overlap).unwrap();
+        assert_eq!(next, london(2026, 10, 26, 1, 30));
+        assert_eq!(next.with_timezone(&Utc), utc(2026, 10, 26, 1, 30));
+    }
+
+    #[test]
+    fn weekdays_skip_weekend() {
+        let r = recurrence("every weekday at 09:00");
+        // 2026-10-16 is a Friday
+        let next = r.next_after(&london(2026, 10, 16, 9, 0)).unwrap();
+        assert_eq!(next, london(2026, 10, 19, 9, 0));
+
+        // Over the spring forward weekend
+        let next = r.next_after(&london(2026, 3, 27, 9, 0)).unwrap();
+        assert_eq!(next.with_timezone(&Utc), utc(2026, 3, 30, 8, 0));
+    }
+
+    #[test]
+    fn align_moves_weekend_start_to_monday() {
+        let r = recurrence("every weekday");
+        assert_eq!(
+            r.align(london(2026, 10, 17, 10, 0)),
+            Some(london(2026, 10, 19, 10, 0))
+        );
+        assert_eq!(
+            r.align(london(2026, 10, 16, 10, 0)),
+            Some(london(2026, 10, 16, 10, 0))
+        );
+    }
+
+    #[test]
+    fn anchored_fixes_time_of_day() {
+        let first = London.with_ymd_and_hms(2026, 10, 16, 9, 15, 30).unwrap();
+        let r = recurrence("every 2 days").anchored(&first);
+        assert_eq!(r.to_string(), "every 2 days at 09:15");
+        assert_eq!(
+            recurrence("every hour").anchored(&first).to_string(),
+            "every hour"
+        );
+    }
+
+    #[test]
+    fn skipping_missed_occurrences() {
+        let r = recurrence("every hour");
+        let next = r
+            .next_after_skipping(&london(2026, 10, 16, 10, 0), &london(2026, 10, 16, 12, 30))
+            .unwrap();
+        assert_eq!(next, london(2026, 10, 16, 13, 0));
+
+        let r = recurrence("every day at 01:30");
+        let next = r
+            .next_after_skipping(&london(2026, 3, 27, 1, 30), &london(2026, 3, 29, 12, 0))
+            .unwrap();
+        assert_eq!(next, london(2026, 3, 30, 1, 30));
+    }
+
+    #[test]
+    fn cron_days_of_week() {
+        assert_eq!(
+            translate_days_of_week("1-5").as_deref(),
+            Some("MON,TUE,WED,THU,FRI")
+        );
+        assert_eq!(translate_days_of_week("0").as_deref(), Some("SUN"));
+        assert_eq!(translate_days_of_week("7").as_deref(), Some("SUN"));
+        assert_eq!(translate_days_of_week("6-7").as_deref(), Some("SAT,SUN"));
+        assert_eq!(
+            translate_days_of_week("*/2").as_deref(),
+            Some("SUN,TUE,THU,SAT")
+        );
+        assert_eq!(
+            translate_days_of_week("MON-FRI").as_deref(),
+            Some("MON-FRI")
+        );
+        assert_eq!(translate_days_of_week("*").as_deref(), Some("*"));
+        assert_eq!(translate_days_of_week("8"), None);
+        assert_eq!(translate_days_of_week("5-1"), None);
+    }
+
+    #[test]
+    fn cron_weekdays_are_monday_to_friday() {
+        let r = recurrence("cron 0 9 * * 1-5");
+        // Friday after 09:00, so the next run is on Monday
+        let next = r.next_after(&london(2026, 10, 16, 10, 0)).unwrap();
+        assert_eq!(next, london(2026, 10, 19, 9, 0));
+
+        let r = recurrence("cron 30 8 * * 0");
+        let next = r.next_after(&london(2026, 10, 16, 10, 0)).unwrap();
+        assert_eq!(next, london(2026, 10, 18, 8, 30));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in [
+            "every minute",
+            "every 5 minutes",
+            "every hour",
+            "every 12 hours",
+            "every day",
+            "every 3 days at 07:45",
+            "every week",
+            "every 2 weeks at 18:00",
+            "every weekday",
+            "every weekday at 09:00",
+            "cron 0 9 * * 1-5",
+        ] {
+            let r = recurrence(s);
+            assert_eq!(r.to_string(), s);
+            assert_eq!(recurrence(&r.to_string()).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn display_normalises_input() {
+        assert_eq!(recurrence("every 2h").to_string(), "every 2 hours");
+        assert_eq!(recurrence("EVERY 14 days").to_string(), "every 2 weeks");
+        assert_eq!(recurrence("every 1 minutes").to_string(), "every minute");
+    }
+
+    #[test]
+    fn time_of_day_only_for_daily_and_weekday() {
+        assert!("every 2 hours at 09:00".parse::<Recurrence>().is_err());
+        assert!("every day at 25:00".parse::<Recurrence>().is_err());
+        assert!("every day tomorrow".parse::<Recurrence>().is_err());
+    }
+}
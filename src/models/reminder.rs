@@ -1,24 +1,54 @@
+use super::recurrence::Recurrence;
 use super::DB;
 use crate::schema::reminders;
 use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, TimeZone, Utc};
-use diesel::{ExpressionMethods, Insertable, Queryable};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, Insertable, OptionalExtension, QueryDsl,
+    Queryable, RunQueryDsl,
+};
+use log::error;
+use serenity::builder::CreateComponents;
 use serenity::client::Context as SContext;
 use serenity::model::channel::{Channel, Message};
+use serenity::model::id::{RoleId, UserId};
+use serenity::model::misc::Mentionable;
 use std::fmt::Debug;
 
+/// How overdue a reminder must be when it is delivered to be flagged as late
+const LATE_THRESHOLD: Duration = Duration::minutes(2);
+
+/// Who a reminder is delivered to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReminderTarget {
+    /// The user who set the reminder
+    Creator,
+    User(u64),
+    Role(u64),
+}
+
 #[derive(Clone, Debug)]
 pub struct Reminder<TZ>
 where
     TZ: TimeZone,
 {
     reminder_id: Option<i64>,
+    /// The user who set the reminder
     pub user_id: u64,
-    pub guild_id: u64,
+    pub target: ReminderTarget,
+    /// The guild the reminder was set in, or `None` if it is delivered by DM
+    pub guild_id: Option<u64>,
     pub channel_id: u64,
     pub reminder_time: DateTime<TZ>,
     pub reminder_text: String,
     pub triggered: bool,
+    pub recurrence: Option<Recurrence>,
+    /// Failed delivery attempts since the last successful delivery
+    pub failed_attempts: i32,
+    /// When to next try delivering after a failed attempt
+    pub retry_at: Option<DateTime<Utc>>,
+    /// Delivery failed permanently, including the fallback to a DM
+    pub failed: bool,
 }
 
 pub enum ReminderTriggerOutcome {
@@ -28,9 +58,21 @@ pub enum ReminderTriggerOutcome {
 }
 
 impl<TZ: TimeZone> Reminder<TZ> {
+    pub fn id(&self) -> Option<i64> {
+        self.reminder_id
+    }
+
+    /// The user who is reminded, or for role reminders, the user who set it
+    pub fn recipient_id(&self) -> u64 {
+        match self.target {
+            ReminderTarget::User(id) => id,
+            ReminderTarget::Creator | ReminderTarget::Role(_) => self.user_id,
+        }
+    }
+
     pub fn new(
         user_id: u64,
-        guild_id: u64,
+        guild_id: Option<u64>,
         channel_id: u64,
         reminder_time: DateTime<TZ>,
         reminder_text: String,
@@ -38,30 +80,34 @@ impl<TZ: TimeZone> Reminder<TZ> {
         Self {
             reminder_id: None,
             user_id,
+            target: ReminderTarget::Creator,
             guild_id,
             channel_id,
             reminder_time,
             reminder_text,
             triggered: false,
+            recurrence: None,
+            failed_attempts: 0,
+            retry_at: None,
+            failed: false,
         }
     }
 
-    pub fn from_message(
-        msg: &Message,
-        reminder_time: DateTime<TZ>,
-        reminder_text: String,
-    ) -> Option<Self> {
-        let guild_id = msg.guild_id?.0;
-        Some(Self::new(
+    pub fn from_message(msg: &Message, reminder_time: DateTime<TZ>, reminder_text: String) -> Self {
+        Self::new(
             msg.author.id.0,
-            guild_id,
+            msg.guild_id.map(|g| g.0),
             msg.channel_id.0,
             reminder_time,
             reminder_text,
-        ))
+        )
     }
 
-    pub async fn trigger(&mut self, ctx: &SContext) -> Result<ReminderTriggerOutcome> {
+    pub async fn trigger(
+        &mut self,
+        ctx: &SContext,
+        components: Option<CreateComponents>,
+    ) -> Result<ReminderTriggerOutcome> {
         if self.triggered {
             return Ok(ReminderTriggerOutcome::AlreadyTriggered);
         }
@@ -72,6 +118,22 @@ impl<TZ: TimeZone> Reminder<TZ> {
             return Ok(ReminderTriggerOutcome::TooEarly);
         }
 
+        let guild_id = match self.guild_id {
+            Some(guild_id) => guild_id,
+            None => {
+                let content = format!(
+                    "Reminder{}: {}{}",
+                    self.sender_note(),
+                    self.reminder_text,
+                    self.late_note(now)
+                );
+                self.send_dm(ctx, content, components).await?;
+                self.triggered = true;
+
+                return Ok(ReminderTriggerOutcome::Success);
+            }
+        };
+
         let channel = ctx
             .http
             .get_channel(self.channel_id)
@@ -85,26 +147,52 @@ impl<TZ: TimeZone> Reminder<TZ> {
             })?;
 
         if let Channel::Guild(gc) = channel {
-            let member = ctx
-                .http
-                .get_member(self.guild_id, self.user_id)
-                .await
-                .with_context(|| {
-                    anyhow!(
-                        "Could not find member {} in guild {} for reminder {:?}",
-                        self.user_id,
-                        self.guild_id,
-                        self.reminder_id
-                    )
-                })?;
-
-            let content = format!("Reminding {}: {}", member, self.reminder_text);
-
-            gc.say(ctx, content).await.with_context(|| {
+            let mention = match self.target {
+                ReminderTarget::Role(role_id) => RoleId(role_id).mention().to_string(),
+                ReminderTarget::Creator | ReminderTarget::User(_) => {
+                    let recipient = self.recipient_id();
+                    let member = ctx
+                        .http
+                        .get_member(guild_id, recipient)
+                        .await
+                        .with_context(|| {
+                            anyhow!(
+                                "Could not find member {} in guild {} for reminder {:?}",
+                                recipient,
+                                guild_id,
+                                self.reminder_id
+                            )
+                        })?;
+                    member.mention().to_string()
+                }
+            };
+
+            let content = format!(
+                "Reminding {}{}: {}{}",
+                mention,
+                self.sender_note(),
+                self.reminder_text,
+                self.late_note(now)
+            );
+
+            gc.send_message(ctx, |m| {
+                m.content(content);
+                // Only ping whoever the reminder is for, not anyone mentioned in its text
+                m.allowed_mentions(|a| match self.target {
+                    ReminderTarget::Role(role_id) => a.empty_parse().roles(vec![role_id]),
+                    _ => a.empty_parse().users(vec![self.recipient_id()]),
+                });
+                if let Some(components) = components {
+                    m.set_components(components);
+                }
+                m
+            })
+            .await
+            .with_context(|| {
                 anyhow!(
                     "Failed to send reminder message for reminder {:?} in guild {} channel {}",
                     self.reminder_id,
-                    self.guild_id,
+                    guild_id,
                     self.channel_id
                 )
             })?;
@@ -120,25 +208,273 @@ impl<TZ: TimeZone> Reminder<TZ> {
             ))
         }
     }
+
+    /// Deliver the reminder by DM, for when it can no longer be posted where it was set
+    pub async fn trigger_by_dm(
+        &mut self,
+        ctx: &SContext,
+        components: Option<CreateComponents>,
+    ) -> Result<()> {
+        let now = Utc::now();
+        let content = format!(
+            "I couldn't post your reminder in <#{}>, so here it is: {}{}",
+            self.channel_id,
+            self.reminder_text,
+            self.late_note(now)
+        );
+
+        self.send_dm(ctx, content, components).await?;
+        self.triggered = true;
+
+        Ok(())
+    }
+
+    async fn send_dm(
+        &self,
+        ctx: &SContext,
+        content: String,
+        components: Option<CreateComponents>,
+    ) -> Result<()> {
+        let recipient = self.recipient_id();
+        let dm = UserId(recipient)
+            .create_dm_channel(ctx)
+            .await
+            .with_context(|| anyhow!("Could not open a DM with user {}", recipient))?;
+
+        dm.send_message(ctx, |m| {
+            m.content(content);
+            if let Some(components) = components {
+                m.set_components(components);
+            }
+            m
+        })
+        .await
+        .with_context(|| {
+            anyhow!(
+                "Failed to send reminder {:?} to user {} by DM",
+                self.reminder_id,
+                recipient
+            )
+        })?;
+
+        Ok(())
+    }
+
+    fn sender_note(&self) -> String {
+        match self.target {
+            ReminderTarget::Creator => String::new(),
+            _ => format!(" (from {})", UserId(self.user_id).mention()),
+        }
+    }
+
+    fn late_note(&self, now: DateTime<Utc>) -> String {
+        if now.signed_duration_since(self.reminder_time.clone()) > LATE_THRESHOLD {
+            format!(
+                "\n*(Sorry, this is late, it was due <t:{}:R>)*",
+                self.reminder_time.timestamp()
+            )
+        } else {
+            String::new()
+        }
+    }
+}
+
+impl Reminder<Utc> {
+    pub fn get<C>(conn: &C, id: i64) -> Result<Option<Self>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        reminders
+            .find(id)
+            .first(conn)
+            .optional()
+            .with_context(|| anyhow!("Failed to find reminder with ID {}", id))
+    }
+
+    pub fn get_pending_for_user<C>(
+        conn: &C,
+        user: u64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        reminders
+            .filter(user_id.eq(user as i64))
+            .filter(triggered.eq(false))
+            .order((reminder_time.asc(), reminder_id.asc()))
+            .offset(offset)
+            .limit(limit)
+            .load(conn)
+            .with_context(|| anyhow!("Failed to load reminders for user {}", user))
+    }
+
+    pub fn count_pending_for_user<C>(conn: &C, user: u64) -> Result<i64>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        reminders
+            .filter(user_id.eq(user as i64))
+            .filter(triggered.eq(false))
+            .count()
+            .get_result(conn)
+            .with_context(|| anyhow!("Failed to count reminders for user {}", user))
+    }
+
+    pub fn delete_pending_for_user<C>(conn: &C, user: u64) -> Result<usize>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        diesel::delete(
+            reminders
+                .filter(user_id.eq(user as i64))
+                .filter(triggered.eq(false)),
+        )
+        .execute(conn)
+        .with_context(|| anyhow!("Failed to delete reminders for user {}", user))
+    }
+
+    /// Delete pending reminders set by other people for `user`
+    pub fn delete_pending_from_others<C>(conn: &C, user: u64) -> Result<usize>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        diesel::delete(
+            reminders
+                .filter(target_user_id.eq(user as i64))
+                .filter(user_id.ne(user as i64))
+                .filter(triggered.eq(false)),
+        )
+        .execute(conn)
+        .with_context(|| anyhow!("Failed to delete reminders from others for user {}", user))
+    }
+
+    pub fn get_due<C>(conn: &C, now: DateTime<Utc>) -> Result<Vec<Self>>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        reminders
+            .filter(triggered.eq(false))
+            .filter(failed.eq(false))
+            .filter(reminder_time.le(now))
+            .filter(retry_at.is_null().or(retry_at.le(now)))
+            .order(reminder_time.asc())
+            .load(conn)
+            .with_context(|| anyhow!("Failed to load reminders due before {}", now))
+    }
+
+    pub fn delete<C>(self, conn: &C) -> Result<()>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        if let Some(id) = self.reminder_id {
+            diesel::delete(reminders.find(id))
+                .execute(conn)
+                .map(|_| ())
+                .with_context(|| anyhow!("Failed to delete reminder with ID {}", id))
+        } else {
+            Err(anyhow!("Reminder to delete had no ID"))
+        }
+    }
+
+    pub fn update<C>(&self, conn: &C) -> Result<()>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::reminders::dsl::*;
+
+        if let Some(id) = self.reminder_id {
+            diesel::update(reminders.find(id))
+                .set((
+                    reminder_time.eq(self.reminder_time),
+                    triggered.eq(self.triggered),
+                    failed_attempts.eq(self.failed_attempts),
+                    retry_at.eq(self.retry_at),
+                    failed.eq(self.failed),
+                ))
+                .execute(conn)
+                .map(|_| ())
+                .with_context(|| anyhow!("Failed to update reminder with ID {}", id))
+        } else {
+            Err(anyhow!("Reminder to update had no ID"))
+        }
+    }
 }
 
 impl Queryable<reminders::SqlType, DB> for Reminder<Utc> {
-    type Row = (i64, i64, i64, i64, DateTime<Utc>, String, bool);
+    type Row = (
+        i64,
+        i64,
+        Option<i64>,
+        i64,
+        DateTime<Utc>,
+        String,
+        bool,
+        Option<String>,
+        i32,
+        Option<DateTime<Utc>>,
+        bool,
+        Option<i64>,
+        Option<i64>,
+    );
 
     fn build(row: Self::Row) -> Self {
-        let (reminder_id, user_id, guild_id, channel_id, reminder_time, reminder_text, triggered) =
-            row;
+        let (
+            reminder_id,
+            user_id,
+            guild_id,
+            channel_id,
+            reminder_time,
+            reminder_text,
+            triggered,
+            recurrence,
+            failed_attempts,
+            retry_at,
+            failed,
+            target_user_id,
+            target_role_id,
+        ) = row;
         let user_id = user_id as u64;
-        let guild_id = guild_id as u64;
+        let target = match (target_user_id, target_role_id) {
+            (Some(id), _) => ReminderTarget::User(id as u64),
+            (None, Some(id)) => ReminderTarget::Role(id as u64),
+            (None, None) => ReminderTarget::Creator,
+        };
+        let guild_id = guild_id.map(|g| g as u64);
         let channel_id = channel_id as u64;
+        let recurrence = recurrence.and_then(|r| {
+            r.parse()
+                .map_err(|e| error!("Invalid recurrence for reminder {}: {:?}", reminder_id, e))
+                .ok()
+        });
         Reminder {
             reminder_id: Some(reminder_id),
             user_id,
+            target,
             guild_id,
             channel_id,
             reminder_time,
             reminder_text,
             triggered,
+            recurrence,
+            failed_attempts,
+            retry_at,
+            failed,
         }
     }
 }
@@ -149,21 +485,34 @@ where
 {
     type Values = <(
         diesel::dsl::Eq<reminders::user_id, i64>,
-        diesel::dsl::Eq<reminders::guild_id, i64>,
+        diesel::dsl::Eq<reminders::guild_id, Option<i64>>,
         diesel::dsl::Eq<reminders::channel_id, i64>,
         diesel::dsl::Eq<reminders::reminder_time, DateTime<Utc>>,
         diesel::dsl::Eq<reminders::reminder_text, String>,
         diesel::dsl::Eq<reminders::triggered, bool>,
+        Option<diesel::dsl::Eq<reminders::recurrence, String>>,
+        Option<diesel::dsl::Eq<reminders::target_user_id, i64>>,
+        Option<diesel::dsl::Eq<reminders::target_role_id, i64>>,
     ) as Insertable<reminders::table>>::Values;
 
     fn values(self) -> Self::Values {
         (
             reminders::user_id.eq(self.user_id as i64),
-            reminders::guild_id.eq(self.guild_id as i64),
+            reminders::guild_id.eq(self.guild_id.map(|g| g as i64)),
             reminders::channel_id.eq(self.channel_id as i64),
             reminders::reminder_time.eq(self.reminder_time.with_timezone(&Utc)),
             reminders::reminder_text.eq(self.reminder_text),
             reminders::triggered.eq(self.triggered),
+            self.recurrence
+                .map(|r| reminders::recurrence.eq(r.to_string())),
+            match self.target {
+                ReminderTarget::User(id) => Some(reminders::target_user_id.eq(id as i64)),
+                _ => None,
+            },
+            match self.target {
+                ReminderTarget::Role(id) => Some(reminders::target_role_id.eq(id as i64)),
+                _ => None,
+            },
         )
             .values()
     }
@@ -11,6 +11,8 @@ use std::fmt::Debug;
 pub struct User {
     pub user_id: u64,
     pub timezone: Option<Tz>,
+    /// Whether other people may set reminders for this user
+    pub reminders_from_others: bool,
 }
 
 impl User {
@@ -18,13 +20,7 @@ impl User {
         Self {
             user_id: id,
             timezone: None,
-        }
-    }
-
-    pub fn new_with_timezone(id: u64, timezone: Tz) -> Self {
-        Self {
-            user_id: id,
-            timezone: Some(timezone),
+            reminders_from_others: true,
         }
     }
 
@@ -52,6 +48,21 @@ impl User {
         Ok(user)
     }
 
+    pub fn update<C>(&self, conn: &C) -> Result<()>
+    where
+        C: Connection<Backend = DB>,
+    {
+        use crate::schema::users::dsl::*;
+        diesel::update(users.find(self.user_id as i64))
+            .set((
+                timezone.eq(self.timezone.map(|tz| tz.name().to_string())),
+                reminders_from_others.eq(self.reminders_from_others),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .with_context(|| anyhow!("Failed to update user {}", self.user_id))
+    }
+
     pub fn get_or_create<C>(conn: &C, id: u64) -> Result<Self>
     where
         C: Connection<Backend = DB>,
@@ -60,15 +71,20 @@ impl User {
             .or_else(|_| Self::create(conn, id))
             .with_context(|| anyhow!("Failed to get or create user {}", id))
     }
+
+    pub fn timezone_or_utc(&self) -> Tz {
+        self.timezone.unwrap_or(Tz::UTC)
+    }
 }
 
 impl Queryable<users::SqlType, DB> for User {
-    type Row = (i64, Option<String>);
+    type Row = (i64, Option<String>, bool);
 
     fn build(row: Self::Row) -> Self {
         User {
             user_id: row.0 as u64,
             timezone: row.1.and_then(|s| s.parse::<Tz>().ok()),
+            reminders_from_others: row.2,
         }
     }
 }
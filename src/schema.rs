@@ -2,9 +2,27 @@ table! {
     aliases (alias_id) {
         alias_id -> Int8,
         user_id -> Int8,
-        guild_id -> Int8,
+        guild_id -> Nullable<Int8>,
         command_name -> Text,
         command_text -> Text,
+        created_at -> Timestamptz,
+        is_macro -> Bool,
+        command_key -> Text,
+        use_count -> Int8,
+        last_used_at -> Nullable<Timestamptz>,
+        attachment_urls -> Array<Text>,
+        embed_json -> Nullable<Text>,
+        scope -> Text,
+    }
+}
+
+table! {
+    guild_settings (guild_id) {
+        guild_id -> Int8,
+        alias_permission -> Text,
+        alias_role_id -> Nullable<Int8>,
+        max_aliases_per_user -> Nullable<Int4>,
+        max_alias_length -> Nullable<Int4>,
     }
 }
 
@@ -12,11 +30,17 @@ table! {
     reminders (reminder_id) {
         reminder_id -> Int8,
         user_id -> Int8,
-        guild_id -> Int8,
+        guild_id -> Nullable<Int8>,
         channel_id -> Int8,
         reminder_time -> Timestamptz,
         reminder_text -> Text,
         triggered -> Bool,
+        recurrence -> Nullable<Text>,
+        failed_attempts -> Int4,
+        retry_at -> Nullable<Timestamptz>,
+        failed -> Bool,
+        target_user_id -> Nullable<Int8>,
+        target_role_id -> Nullable<Int8>,
     }
 }
 
@@ -24,10 +48,11 @@ table! {
     users (user_id) {
         user_id -> Int8,
         timezone -> Nullable<Text>,
+        reminders_from_others -> Bool,
     }
 }
 
 joinable!(aliases -> users (user_id));
 joinable!(reminders -> users (user_id));
 
-allow_tables_to_appear_in_same_query!(aliases, reminders, users,);
+allow_tables_to_appear_in_same_query!(aliases, guild_settings, reminders, users,);
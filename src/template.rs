@@ -0,0 +1,208 @@
+//! Expansion of alias templates such as `Hello {1:world}, from {author}`
+//!
+//! Placeholders are:
+//! - `{1}`, `{2}`, ... for the words after the alias name
+//! - `{rest}` for all the text after the alias name
+//! - `{author}` and `{channel}` for where the alias was used
+//! - `{random:a|b|c}` for one of the options at random
+//!
+//! Arguments can have a default used when they are missing, e.g. `{1:world}`. `{{` and `}}` are
+//! literal braces, and anything else in braces is left as it is.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Longest text a template can expand to, which is Discord's message length limit
+pub const MAX_OUTPUT_CHARS: usize = 2000;
+
+/// Values available to a template when it is expanded
+pub struct TemplateContext<'a> {
+    /// The text after the alias name in the invoking message
+    pub args: &'a str,
+    pub author: &'a str,
+    pub channel: &'a str,
+}
+
+/// Expand `template` into a message, breaking up any mentions in the placeholder values
+pub fn render<R>(template: &str, ctx: &TemplateContext, rng: &mut R) -> String
+where
+    R: Rng + ?Sized,
+{
+    render_with_escaping(template, ctx, rng, true)
+}
+
+/// Expand `template` into a command to run, leaving mentions intact so the command can parse them
+///
+/// The command chooses which of the mentions in its own reply can ping anyone.
+pub fn render_command<R>(template: &str, ctx: &TemplateContext, rng: &mut R) -> String
+where
+    R: Rng + ?Sized,
+{
+    render_with_escaping(template, ctx, rng, false)
+}
+
+fn render_with_escaping<R>(
+    template: &str,
+    ctx: &TemplateContext,
+    rng: &mut R,
+    escape: bool,
+) -> String
+where
+    R: Rng + ?Sized,
+{
+    let args = ctx.args.split_whitespace().collect::<Vec<_>>();
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        // Stop early so that repeated placeholders cannot build up a huge string
+        if output.chars().count() > MAX_OUTPUT_CHARS {
+            break;
+        }
+
+        output.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        let after = &rest[start + 1..];
+
+        if after.starts_with(brace) {
+            output.push_str(brace);
+            rest = &after[1..];
+        } else if brace == "}" {
+            output.push_str(brace);
+            rest = after;
+        } else if let Some(end) = after.find('}') {
+            match expand(&after[..end], &args, ctx, rng) {
+                Some(value) if escape => output.push_str(&escape_mentions(&value)),
+                Some(value) => output.push_str(&value),
+                None => output.push_str(&rest[start..start + end + 2]),
+            }
+            rest = &after[end + 1..];
+        } else {
+            output.push_str(&rest[start..]);
+            rest = "";
+        }
+    }
+    output.push_str(rest);
+
+    truncate(output)
+}
+
+/// The value of a placeholder, or `None` if it is not one
+fn expand<R>(placeholder: &str, args: &[&str], ctx: &TemplateContext, rng: &mut R) -> Option<String>
+where
+    R: Rng + ?Sized,
+{
+    let (name, default) = match placeholder.split_once(':') {
+        Some((name, default)) => (name, Some(default)),
+        None => (placeholder, None),
+    };
+
+    let value = match name.trim() {
+        "author" => Some(ctx.author),
+        "channel" => Some(ctx.channel),
+        "random" => {
+            let options = default?.split('|').collect::<Vec<_>>();
+            return options.choose(rng).map(|s| s.to_string());
+        }
+        "rest" => Some(ctx.args.trim()).filter(|s| !s.is_empty()),
+        n => {
+            let index = n.parse::<usize>().ok().filter(|&i| i > 0)?;
+            args.get(index - 1).copied()
+        }
+    };
+
+    Some(value.or(default).unwrap_or_default().to_string())
+}
+
+/// Stop text from pinging anyone by breaking up `@everyone`, `@here` and user or role mentions
+fn escape_mentions(s: &str) -> String {
+    s.replace('@', "@\u{200B}")
+}
+
+fn truncate(s: String) -> String {
+    if s.chars().count() <= MAX_OUTPUT_CHARS {
+        return s;
+    }
+
+    let mut truncated = s.chars().take(MAX_OUTPUT_CHARS - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn render_with(template: &str, args: &str) -> String {
+        let ctx = TemplateContext {
+            args,
+            author: "alice",
+            channel: "<#1234>",
+        };
+        render(template, &ctx, &mut StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn positional_arguments() {
+        assert_eq!(render_with("{2} {1}", "hello world"), "world hello");
+        assert_eq!(render_with("[{3}]", "hello world"), "[]");
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(render_with("hello {1:world}", ""), "hello world");
+        assert_eq!(render_with("hello {1:world}", "there"), "hello there");
+        assert_eq!(render_with("{rest:nothing}", "  "), "nothing");
+    }
+
+    #[test]
+    fn rest_and_context() {
+        assert_eq!(
+            render_with("{author} in {channel} says {rest}", " a  b "),
+            "alice in <#1234> says a  b"
+        );
+    }
+
+    #[test]
+    fn random_choice() {
+        let output = render_with("{random:a|b|c}", "");
+        assert!(["a", "b", "c"].contains(&output.as_str()));
+    }
+
+    #[test]
+    fn literal_and_unknown_braces() {
+        assert_eq!(
+            render_with("{{1}} {unknown} {0} {", "x"),
+            "{1} {unknown} {0} {"
+        );
+        assert_eq!(render_with("a } b", ""), "a } b");
+    }
+
+    #[test]
+    fn mentions_are_escaped() {
+        assert_eq!(render_with("{1}", "@everyone"), "@\u{200B}everyone");
+        assert_eq!(render_with("{rest}", "<@1234>"), "<@\u{200B}1234>");
+    }
+
+    #[test]
+    fn commands_keep_mentions() {
+        let ctx = TemplateContext {
+            args: "<@1234> @everyone",
+            author: "alice",
+            channel: "<#1234>",
+        };
+        assert_eq!(
+            render_command("{1} {2}", &ctx, &mut StdRng::seed_from_u64(0)),
+            "<@1234> @everyone"
+        );
+    }
+
+    #[test]
+    fn output_is_capped() {
+        let output = render_with(&"{rest}".repeat(100), &"x".repeat(1000));
+        assert_eq!(output.chars().count(), MAX_OUTPUT_CHARS);
+        assert!(output.ends_with('…'));
+    }
+}
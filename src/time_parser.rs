@@ -0,0 +1,898 @@
+//! Parsing of human friendly times such as `in 3 days 4h`, `tomorrow at 9am`, `next friday 17:30`
+//! or `2026-11-01T08:00`, relative to the current time in the user's timezone.
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Weekday,
+};
+use std::fmt::{Display, Formatter};
+
+// Roughly a century, keeps the arithmetic well within the range of `Duration`
+const MAX_DURATION_SECONDS: i64 = 100 * 365 * 24 * 60 * 60;
+
+// Used when a date is given without a time
+const DEFAULT_HOUR: u32 = 9;
+
+const EXPECTED_TIME: &str = "a time such as `in 2h`, `tomorrow at 9am` or `2026-11-01T08:00`";
+const EXPECTED_DURATION: &str = "a duration such as `2h`, `3 days` or `an hour`";
+const EXPECTED_UNIT: &str = "a unit of time such as `minutes`, `hours` or `d`";
+const EXPECTED_TIME_OF_DAY: &str = "a time of day such as `9am`, `17:30` or `noon`";
+const EXPECTED_DATE: &str = "a date such as `2026-11-01`, `tomorrow` or `friday`";
+const EXPECTED_WEEKDAY: &str = "a day of the week such as `friday`";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimeParseError {
+    /// The input ended where more was expected
+    UnexpectedEnd {
+        position: usize,
+        expected: &'static str,
+    },
+    /// A token could not be understood
+    UnexpectedToken {
+        position: usize,
+        token: String,
+        expected: &'static str,
+    },
+    /// The local time does not exist in the timezone, i.e. it is skipped by a DST transition
+    NonexistentTime(NaiveDateTime),
+    /// The parsed time is not after the current time
+    NotInFuture,
+    /// The parsed time is too far away to be represented
+    TooFar,
+}
+
+impl TimeParseError {
+    /// Render `input` with the offending token underlined, if the error refers to one
+    pub fn highlight(&self, input: &str) -> Option<String> {
+        let (position, width) = match self {
+            TimeParseError::UnexpectedEnd { position, .. } => (*position, 1),
+            TimeParseError::UnexpectedToken {
+                position, token, ..
+            } => (*position, token.chars().count()),
+            _ => return None,
+        };
+        let offset = input.get(..position)?.chars().count();
+        Some(format!(
+            "{}\n{}{}",
+            input,
+            " ".repeat(offset),
+            "^".repeat(width.max(1))
+        ))
+    }
+}
+
+impl Display for TimeParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeParseError::UnexpectedEnd { expected, .. } => {
+                write!(f, "Expected {} but the input ended", expected)
+            }
+            TimeParseError::UnexpectedToken {
+                token, expected, ..
+            } => write!(f, "Could not understand `{}`, expected {}", token, expected),
+            TimeParseError::NonexistentTime(time) => {
+                write!(f, "{} does not exist in your timezone", time)
+            }
+            TimeParseError::NotInFuture => write!(f, "That time is not in the future"),
+            TimeParseError::TooFar => write!(f, "That time is too far in the future"),
+        }
+    }
+}
+
+impl std::error::Error for TimeParseError {}
+
+/// Parse a time from the start of `input`, returning it along with the unparsed remainder
+pub fn parse_time<'a, TZ>(
+    input: &'a str,
+    now: &DateTime<TZ>,
+) -> Result<(DateTime<TZ>, &'a str), TimeParseError>
+where
+    TZ: TimeZone,
+{
+    let mut parser = Parser::new(input);
+
+    let time = if parser.peek_word().as_deref() == Some("in") {
+        parser.advance();
+        let duration = parser.duration()?;
+        now.clone()
+            .checked_add_signed(duration)
+            .ok_or(TimeParseError::TooFar)?
+    } else {
+        parser.absolute(now)?
+    };
+
+    if time <= *now {
+        Err(TimeParseError::NotInFuture)
+    } else {
+        Ok((time, parser.remainder()))
+    }
+}
+
+struct Token<'a> {
+    text: &'a str,
+    start: usize,
+}
+
+impl Token<'_> {
+    /// The lowercased token, ignoring a trailing comma
+    fn word(&self) -> String {
+        self.text.trim_end_matches(',').to_lowercase()
+    }
+}
+
+fn tokenise(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    for (i, c) in input.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push(Token {
+                    text: &input[s..i],
+                    start: s,
+                });
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+
+    if let Some(s) = start {
+        tokens.push(Token {
+            text: &input[s..],
+            start: s,
+        });
+    }
+
+    tokens
+}
+
+enum Component {
+    Date { date: NaiveDate, repeat_days: i64 },
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token<'a>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            tokens: tokenise(input),
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_word(&self) -> Option<String> {
+        self.peek().map(Token::word)
+    }
+
+    fn advance(&mut self) {
+        self.position += 1;
+    }
+
+    fn remainder(&self) -> &'a str {
+        self.peek().map(|t| &self.input[t.start..]).unwrap_or("")
+    }
+
+    fn unexpected(&self, expected: &'static str) -> TimeParseError {
+        match self.peek() {
+            Some(t) => TimeParseError::UnexpectedToken {
+                position: t.start,
+                token: t.text.to_string(),
+                expected,
+            },
+            None => TimeParseError::UnexpectedEnd {
+                position: self.input.len(),
+                expected,
+            },
+        }
+    }
+
+    /// One or more duration parts, optionally separated by `and`
+    fn duration(&mut self) -> Result<Duration, TimeParseError> {
+        let mut total: i64 = 0;
+        let mut parts = 0;
+
+        loop {
+            let checkpoint = self.position;
+            if parts > 0 && self.peek_word().as_deref() == Some("and") {
+                self.advance();
+            }
+
+            match self.duration_part() {
+                Ok(Some(seconds)) => {
+                    total = total
+                        .checked_add(seconds)
+                        .filter(|&t| t <= MAX_DURATION_SECONDS)
+                        .ok_or(TimeParseError::TooFar)?;
+                    parts += 1;
+                }
+                // A word like `3x` after a complete duration is a mistyped part of it
+                Err(e) if parts > 0 && self.at_compact_duration() => return Err(e),
+                // Anything else after a complete duration is the start of the reminder text
+                Ok(None) | Err(_) if parts > 0 => {
+                    self.position = checkpoint;
+                    break;
+                }
+                Ok(None) => return Err(self.unexpected(EXPECTED_DURATION)),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(Duration::seconds(total))
+    }
+
+    /// Whether the current token is a number run together with a unit, such as `1d12h`
+    fn at_compact_duration(&self) -> bool {
+        self.peek_word().is_some_and(|w| {
+            w.starts_with(|c: char| c.is_ascii_digit()) && !w.chars().all(|c| c.is_ascii_digit())
+        })
+    }
+
+    /// A duration part such as `3 days`, `an hour` or `1d12h`, in seconds
+    fn duration_part(&mut self) -> Result<Option<i64>, TimeParseError> {
+        let token = match self.peek() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let word = token.word();
+
+        if word == "a" || word == "an" {
+            self.advance();
+            return self.unit().map(Some);
+        }
+
+        if !word.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(None);
+        }
+
+        if word.chars().all(|c| c.is_ascii_digit()) {
+            let amount = word.parse::<i64>().map_err(|_| TimeParseError::TooFar)?;
+            self.advance();
+            let unit = self.unit()?;
+            return amount
+                .checked_mul(unit)
+                .map(Some)
+                .ok_or(TimeParseError::TooFar);
+        }
+
+        let seconds =
+            parse_compact_duration(&word).ok_or_else(|| self.unexpected(EXPECTED_DURATION))?;
+        self.advance();
+        Ok(Some(seconds))
+    }
+
+    fn unit(&mut self) -> Result<i64, TimeParseError> {
+        match self.peek_word().as_deref().and_then(unit_seconds) {
+            Some(seconds) => {
+                self.advance();
+                Ok(seconds)
+            }
+            None => Err(self.unexpected(EXPECTED_UNIT)),
+        }
+    }
+
+    /// A combination of at most one date and one time of day, in either order
+    fn absolute<TZ>(&mut self, now: &DateTime<TZ>) -> Result<DateTime<TZ>, TimeParseError>
+    where
+        TZ: TimeZone,
+    {
+        let today = now.naive_local().date();
+        let mut date = None;
+        let mut time = None;
+        // How far to move a date forward if the time has already passed, e.g. `friday` means
+        // next week's Friday if it is Friday evening
+        let mut repeat_days = 0;
+
+        while let Some(component) = self.component(today, date.is_none(), time.is_none())? {
+            match component {
+                Component::Date {
+                    date: d,
+                    repeat_days: r,
+                } => {
+                    date = Some(d);
+                    repeat_days = r;
+                }
+                Component::Time(t) => time = Some(t),
+                Component::DateTime(dt) => {
+                    date = Some(dt.date());
+                    time = Some(dt.time());
+                }
+            }
+        }
+
+        if date.is_none() && time.is_none() {
+            return Err(self.unexpected(EXPECTED_TIME));
+        }
+
+        if date.is_none() {
+            // A time on its own means the next time the clock shows it
+            repeat_days = 1;
+        }
+
+        let date = date.unwrap_or(today);
+        let time = time.unwrap_or_else(|| NaiveTime::from_hms_opt(DEFAULT_HOUR, 0, 0).unwrap());
+        let result = resolve_local(&now.timezone(), date.and_time(time))?;
+
+        if result <= *now && repeat_days > 0 {
+            let date = date
+                .checked_add_signed(Duration::days(repeat_days))
+                .ok_or(TimeParseError::TooFar)?;
+            resolve_local(&now.timezone(), date.and_time(time))
+        } else {
+            Ok(result)
+        }
+    }
+
+    fn component(
+        &mut self,
+        today: NaiveDate,
+        want_date: bool,
+        want_time: bool,
+    ) -> Result<Option<Component>, TimeParseError> {
+        let word = match self.peek_word() {
+            Some(w) => w,
+            None => return Ok(None),
+        };
+
+        match word.as_ref() {
+            "at" if want_time => {
+                self.advance();
+                if let Some(time) = self.time_of_day(false)? {
+                    return Ok(Some(Component::Time(time)));
+                }
+                // Also allow `at 2026-10-20 14:00`
+                if want_date {
+                    if let Some(component) = self.date(today, true)? {
+                        return Ok(Some(component));
+                    }
+                }
+                Err(self.unexpected(EXPECTED_TIME_OF_DAY))
+            }
+            "on" if want_date => {
+                self.advance();
+                match self.date(today, want_time)? {
+                    Some(component) => Ok(Some(component)),
+                    None => Err(self.unexpected(EXPECTED_DATE)),
+                }
+            }
+            "next" if want_date => {
+                self.advance();
+                match self.peek_word().and_then(|w| w.parse::<Weekday>().ok()) {
+                    Some(weekday) => {
+                        self.advance();
+                        let date = next_weekday(today, weekday, false);
+                        Ok(Some(Component::Date {
+                            date,
+                            repeat_days: 0,
+                        }))
+                    }
+                    None => Err(self.unexpected(EXPECTED_WEEKDAY)),
+                }
+            }
+            _ => {
+                if want_date {
+                    if let Some(component) = self.date(today, want_time)? {
+                        return Ok(Some(component));
+                    }
+                }
+                if want_time {
+                    if let Some(time) = self.time_of_day(true)? {
+                        return Ok(Some(Component::Time(time)));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// `today`, `tomorrow`, a day of the week or an ISO 8601 date, optionally with a time
+    fn date(
+        &mut self,
+        today: NaiveDate,
+        allow_time: bool,
+    ) -> Result<Option<Component>, TimeParseError> {
+        let word = match self.peek_word() {
+            Some(w) => w,
+            None => return Ok(None),
+        };
+
+        let component = match word.as_ref() {
+            "today" => Component::Date {
+                date: today,
+                repeat_days: 0,
+            },
+            "tomorrow" => Component::Date {
+                date: today.succ_opt().ok_or(TimeParseError::TooFar)?,
+                repeat_days: 0,
+            },
+            w => {
+                if let Ok(weekday) = w.parse::<Weekday>() {
+                    Component::Date {
+                        date: next_weekday(today, weekday, true),
+                        repeat_days: 7,
+                    }
+                } else if looks_like_iso_date(w) {
+                    if let Ok(date) = NaiveDate::parse_from_str(w, "%Y-%m-%d") {
+                        Component::Date {
+                            date,
+                            repeat_days: 0,
+                        }
+                    } else if let Some(date_time) =
+                        allow_time.then(|| parse_iso_date_time(w)).flatten()
+                    {
+                        Component::DateTime(date_time)
+                    } else {
+                        return Err(self.unexpected(EXPECTED_DATE));
+                    }
+                } else {
+                    return Ok(None);
+                }
+            }
+        };
+
+        self.advance();
+        Ok(Some(component))
+    }
+
+    /// A time of day such as `noon`, `17:30`, `9am` or `9:30 pm`
+    ///
+    /// Bare numbers are only accepted when `strict` is false, i.e. after `at`, so that reminder
+    /// text starting with a number is not mistaken for a time.
+    fn time_of_day(&mut self, strict: bool) -> Result<Option<NaiveTime>, TimeParseError> {
+        let word = match self.peek_word() {
+            Some(w) => w,
+            None => return Ok(None),
+        };
+
+        match word.as_ref() {
+            "noon" | "midday" => {
+                self.advance();
+                return Ok(NaiveTime::from_hms_opt(12, 0, 0));
+            }
+            "midnight" => {
+                self.advance();
+                return Ok(NaiveTime::from_hms_opt(0, 0, 0));
+            }
+            _ => {}
+        }
+
+        if !word.starts_with(|c: char| c.is_ascii_digit()) || looks_like_iso_date(&word) {
+            return Ok(None);
+        }
+
+        let (clock, inline_meridiem) = split_meridiem(&word);
+        let next_meridiem = if inline_meridiem.is_none() {
+            self.tokens
+                .get(self.position + 1)
+                .and_then(|t| parse_meridiem(&t.word()))
+        } else {
+            None
+        };
+        let meridiem = inline_meridiem.or(next_meridiem);
+
+        if strict && meridiem.is_none() && !clock.contains(':') {
+            return Ok(None);
+        }
+
+        let time =
+            parse_clock(clock, meridiem).ok_or_else(|| self.unexpected(EXPECTED_TIME_OF_DAY))?;
+
+        self.advance();
+        if next_meridiem.is_some() {
+            self.advance();
+        }
+        Ok(Some(time))
+    }
+}
+
+fn unit_seconds(unit: &str) -> Option<i64> {
+    match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(60),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(60 * 60),
+        "d" | "day" | "days" => Some(24 * 60 * 60),
+        "w" | "wk" | "wks" | "week" | "weeks" => Some(7 * 24 * 60 * 60),
+        _ => None,
+    }
+}
+
+/// Parse durations written without spaces such as `90s`, `2hr` or `1d12h30m`, in seconds
+fn parse_compact_duration(s: &str) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut rest = s;
+
+    while !rest.is_empty() {
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit_end = rest[digits_end..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |i| digits_end + i);
+
+        let amount = rest[..digits_end].parse::<i64>().ok()?;
+        let unit = unit_seconds(&rest[digits_end..unit_end])?;
+        total = amount
+            .checked_mul(unit)
+            .and_then(|seconds| total.checked_add(seconds))?;
+        rest = &rest[unit_end..];
+    }
+
+    Some(total)
+}
+
+fn looks_like_iso_date(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    bytes.len() >= 5 && bytes[..4].iter().all(u8::is_ascii_digit) && bytes[4] == b'-'
+}
+
+fn parse_iso_date_time(s: &str) -> Option<NaiveDateTime> {
+    let s = s.to_uppercase();
+    ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&s, format).ok())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Meridiem {
+    Am,
+    Pm,
+}
+
+fn parse_meridiem(s: &str) -> Option<Meridiem> {
+    match s {
+        "am" | "a.m." => Some(Meridiem::Am),
+        "pm" | "p.m." => Some(Meridiem::Pm),
+        _ => None,
+    }
+}
+
+fn split_meridiem(s: &str) -> (&str, Option<Meridiem>) {
+    ["am", "a.m.", "pm", "p.m."]
+        .iter()
+        .find_map(|suffix| {
+            s.strip_suffix(suffix)
+                .map(|clock| (clock, parse_meridiem(suffix)))
+        })
+        .unwrap_or((s, None))
+}
+
+fn parse_clock(clock: &str, meridiem: Option<Meridiem>) -> Option<NaiveTime> {
+    let (hour, minute) = match clock.split_once(':') {
+        Some((h, m)) if m.len() == 2 => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        Some(_) => return None,
+        None => (clock.parse::<u32>().ok()?, 0),
+    };
+
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(Meridiem::Am) => hour % 12,
+        Some(Meridiem::Pm) => hour % 12 + 12,
+        None => hour,
+    };
+
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// The first date on or after `today` (or strictly after, if `include_today` is false) that
+/// falls on `weekday`
+fn next_weekday(today: NaiveDate, weekday: Weekday, include_today: bool) -> NaiveDate {
+    let mut days = (7 + weekday.num_days_from_monday() as i64
+        - today.weekday().num_days_from_monday() as i64)
+        % 7;
+    if days == 0 && !include_today {
+        days = 7;
+    }
+    today + Duration::days(days)
+}
+
+fn resolve_local<TZ>(tz: &TZ, time: NaiveDateTime) -> Result<DateTime<TZ>, TimeParseError>
+where
+    TZ: TimeZone,
+{
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(t) => Ok(t),
+        // Pick the first occurrence when clocks go back
+        LocalResult::Ambiguous(earliest, _) => Ok(earliest),
+        LocalResult::None => Err(TimeParseError::NonexistentTime(time)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use chrono_tz::Europe::London;
+    use chrono_tz::Tz;
+
+    // Saturday
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, 10, 0, 0).unwrap()
+    }
+
+    fn parse(input: &str) -> (DateTime<Utc>, &str) {
+        parse_time(input, &now()).unwrap_or_else(|e| panic!("Failed to parse {}: {}", input, e))
+    }
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn relative_compact() {
+        assert_eq!(parse("in 2h"), (at(2026, 10, 17, 12, 0), ""));
+        assert_eq!(parse("in 1d2h30m text"), (at(2026, 10, 18, 12, 30), "text"));
+        assert_eq!(parse("in 90s"), (now() + Duration::seconds(90), ""));
+        assert_eq!(parse("in 2hr"), (at(2026, 10, 17, 12, 0), ""));
+    }
+
+    #[test]
+    fn relative_words() {
+        assert_eq!(parse("in 3 days 4h"), (at(2026, 10, 20, 14, 0), ""));
+        assert_eq!(
+            parse("in an hour do it"),
+            (at(2026, 10, 17, 11, 0), "do it")
+        );
+        assert_eq!(parse("in a week"), (at(2026, 10, 24, 10, 0), ""));
+        assert_eq!(
+            parse("in 1 hour and 30 minutes"),
+            (at(2026, 10, 17, 11, 30), "")
+        );
+        assert_eq!(parse("IN 5 MINS"), (at(2026, 10, 17, 10, 5), ""));
+    }
+
+    #[test]
+    fn relative_stops_at_text() {
+        assert_eq!(
+            parse("in 2h 4 apples"),
+            (at(2026, 10, 17, 12, 0), "4 apples")
+        );
+        assert_eq!(
+            parse("in 2h and then"),
+            (at(2026, 10, 17, 12, 0), "and then")
+        );
+        assert_eq!(
+            parse("in 10m   check  the oven"),
+            (at(2026, 10, 17, 10, 10), "check  the oven")
+        );
+    }
+
+    #[test]
+    fn relative_errors() {
+        assert_eq!(
+            parse_time("in 1h 3x do it", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 6,
+                token: "3x".to_string(),
+                expected: EXPECTED_DURATION,
+            })
+        );
+        assert_eq!(
+            parse_time("in 3 dayz", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 5,
+                token: "dayz".to_string(),
+                expected: EXPECTED_UNIT,
+            })
+        );
+        assert_eq!(
+            parse_time("in 3x", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 3,
+                token: "3x".to_string(),
+                expected: EXPECTED_DURATION,
+            })
+        );
+        assert_eq!(
+            parse_time("in soon", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 3,
+                token: "soon".to_string(),
+                expected: EXPECTED_DURATION,
+            })
+        );
+        assert_eq!(
+            parse_time("in", &now()),
+            Err(TimeParseError::UnexpectedEnd {
+                position: 2,
+                expected: EXPECTED_DURATION,
+            })
+        );
+        assert_eq!(
+            parse_time("in 0s", &now()),
+            Err(TimeParseError::NotInFuture)
+        );
+        assert_eq!(
+            parse_time("in 99999999999999999999 days", &now()),
+            Err(TimeParseError::TooFar)
+        );
+        assert_eq!(
+            parse_time("in 200 weeks 999999w", &now()),
+            Err(TimeParseError::TooFar)
+        );
+    }
+
+    #[test]
+    fn tomorrow_and_today() {
+        assert_eq!(parse("tomorrow at 9am"), (at(2026, 10, 18, 9, 0), ""));
+        assert_eq!(parse("tomorrow 17:30 go"), (at(2026, 10, 18, 17, 30), "go"));
+        assert_eq!(parse("tomorrow"), (at(2026, 10, 18, 9, 0), ""));
+        assert_eq!(parse("today at 3pm"), (at(2026, 10, 17, 15, 0), ""));
+        assert_eq!(parse("at 9:30 pm tomorrow"), (at(2026, 10, 18, 21, 30), ""));
+        assert_eq!(
+            parse_time("today at 8am", &now()),
+            Err(TimeParseError::NotInFuture)
+        );
+    }
+
+    #[test]
+    fn time_only_rolls_over() {
+        assert_eq!(parse("at noon"), (at(2026, 10, 17, 12, 0), ""));
+        assert_eq!(parse("at 9am"), (at(2026, 10, 18, 9, 0), ""));
+        assert_eq!(parse("at midnight"), (at(2026, 10, 18, 0, 0), ""));
+        assert_eq!(parse("at 14"), (at(2026, 10, 17, 14, 0), ""));
+        assert_eq!(parse("at 12am"), (at(2026, 10, 18, 0, 0), ""));
+        assert_eq!(parse("at 12pm"), (at(2026, 10, 17, 12, 0), ""));
+        assert_eq!(parse("11pm, bed"), (at(2026, 10, 17, 23, 0), "bed"));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(parse("next friday 17:30"), (at(2026, 10, 23, 17, 30), ""));
+        assert_eq!(parse("friday"), (at(2026, 10, 23, 9, 0), ""));
+        assert_eq!(parse("on monday at 8am"), (at(2026, 10, 19, 8, 0), ""));
+        // Today is Saturday, so a later time is today and an earlier time is next week
+        assert_eq!(parse("saturday at 11:00"), (at(2026, 10, 17, 11, 0), ""));
+        assert_eq!(parse("sat at 9:00"), (at(2026, 10, 24, 9, 0), ""));
+        assert_eq!(
+            parse("next saturday at 11:00"),
+            (at(2026, 10, 24, 11, 0), "")
+        );
+    }
+
+    #[test]
+    fn iso_dates() {
+        assert_eq!(parse("2026-11-01T08:00 x"), (at(2026, 11, 1, 8, 0), "x"));
+        assert_eq!(parse("2026-11-01T08:00:00"), (at(2026, 11, 1, 8, 0), ""));
+        assert_eq!(parse("2026-11-01"), (at(2026, 11, 1, 9, 0), ""));
+        assert_eq!(
+            parse("at 2026-10-20 14:00 text"),
+            (at(2026, 10, 20, 14, 0), "text")
+        );
+        assert_eq!(parse("on 2026-10-20 at 2pm"), (at(2026, 10, 20, 14, 0), ""));
+        assert_eq!(
+            parse_time("2026-13-01", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 0,
+                token: "2026-13-01".to_string(),
+                expected: EXPECTED_DATE,
+            })
+        );
+        assert_eq!(
+            parse_time("2020-01-01", &now()),
+            Err(TimeParseError::NotInFuture)
+        );
+    }
+
+    #[test]
+    fn bare_numbers_are_not_times() {
+        assert_eq!(
+            parse("tomorrow 5 apples"),
+            (at(2026, 10, 18, 9, 0), "5 apples")
+        );
+    }
+
+    #[test]
+    fn absolute_errors() {
+        assert_eq!(
+            parse_time("at 25:00", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 3,
+                token: "25:00".to_string(),
+                expected: EXPECTED_TIME_OF_DAY,
+            })
+        );
+        assert_eq!(
+            parse_time("at 13pm", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 3,
+                token: "13pm".to_string(),
+                expected: EXPECTED_TIME_OF_DAY,
+            })
+        );
+        assert_eq!(
+            parse_time("at lunch", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 3,
+                token: "lunch".to_string(),
+                expected: EXPECTED_TIME_OF_DAY,
+            })
+        );
+        assert_eq!(
+            parse_time("next week", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 5,
+                token: "week".to_string(),
+                expected: EXPECTED_WEEKDAY,
+            })
+        );
+        assert_eq!(
+            parse_time("soon please", &now()),
+            Err(TimeParseError::UnexpectedToken {
+                position: 0,
+                token: "soon".to_string(),
+                expected: EXPECTED_TIME,
+            })
+        );
+        assert_eq!(
+            parse_time("", &now()),
+            Err(TimeParseError::UnexpectedEnd {
+                position: 0,
+                expected: EXPECTED_TIME,
+            })
+        );
+    }
+
+    #[test]
+    fn user_timezone() {
+        // 11:00 in London during BST
+        let now = London.with_ymd_and_hms(2026, 10, 17, 11, 0, 0).unwrap();
+        let (time, _) = parse_time("at 17:30", &now).unwrap();
+        assert_eq!(
+            time,
+            London.with_ymd_and_hms(2026, 10, 17, 17, 30, 0).unwrap()
+        );
+        assert_eq!(time.with_timezone(&Utc), at(2026, 10, 17, 16, 30));
+
+        // After the clocks go back
+        let (time, _) = parse_time("2026-11-01T08:00", &now).unwrap();
+        assert_eq!(time.with_timezone(&Utc), at(2026, 11, 1, 8, 0));
+    }
+
+    #[test]
+    fn dst_transitions() {
+        let now: DateTime<Tz> = London.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        // Clocks go forward at 01:00 on 2026-03-29
+        assert_eq!(
+            parse_time("2026-03-29T01:30", &now),
+            Err(TimeParseError::NonexistentTime(
+                NaiveDate::from_ymd_opt(2026, 3, 29)
+                    .and_then(|d| d.and_hms_opt(1, 30, 0))
+                    .unwrap()
+            ))
+        );
+        // Clocks go back at 02:00 on 2026-10-25, the earlier (BST) time is used
+        let (time, _) = parse_time("2026-10-25T01:30", &now).unwrap();
+        assert_eq!(time.with_timezone(&Utc), at(2026, 10, 25, 0, 30));
+    }
+
+    #[test]
+    fn highlight() {
+        let input = "in 3 dayz do it";
+        let error = parse_time(input, &now()).unwrap_err();
+        assert_eq!(
+            error.highlight(input).unwrap(),
+            "in 3 dayz do it\n     ^^^^"
+        );
+
+        let input = "in 1h 3x do it";
+        let error = parse_time(input, &now()).unwrap_err();
+        assert_eq!(error.highlight(input).unwrap(), "in 1h 3x do it\n      ^^");
+
+        let error = parse_time("in", &now()).unwrap_err();
+        assert_eq!(error.highlight("in").unwrap(), "in\n  ^");
+
+        assert_eq!(TimeParseError::NotInFuture.highlight("in 0s"), None);
+    }
+}
@@ -1,11 +1,15 @@
+use crate::bot::BotOwnersContainer;
 use crate::PgConnectionContainer;
 use caseless::Caseless;
+use chrono::{DateTime, TimeZone};
 use diesel::PgConnection;
 use serenity::client::Context;
+use std::fmt::Display;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use unicode_normalization::UnicodeNormalization;
 
+/// Key that names are matched by, which the add_alias_command_key migration also computes in SQL
 pub fn compatibility_case_fold(s: &str) -> String {
     s.nfd()
         .default_case_fold()
@@ -15,6 +19,46 @@ pub fn compatibility_case_fold(s: &str) -> String {
         .collect()
 }
 
+/// Levenshtein distance between two strings, counted in `char`s
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Cut `s` down to at most `max_chars` `char`s, ending with an ellipsis if anything was removed
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+
+    let mut truncated = s
+        .chars()
+        .take(max_chars.saturating_sub(1))
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+pub fn format_datetime<TZ>(time: &DateTime<TZ>) -> String
+where
+    TZ: TimeZone,
+    TZ::Offset: Display,
+{
+    time.format("%Y-%m-%d %H:%M %Z").to_string()
+}
+
 pub async fn get_conn(ctx: &Context) -> Arc<Mutex<PgConnection>> {
     let data = ctx.data.read().await;
     data.get::<PgConnectionContainer>().unwrap().clone()
@@ -30,3 +74,65 @@ pub async fn user_is_administrator_in_guild(ctx: &Context, guild_id: u64, user_i
         _ => false,
     }
 }
+
+pub async fn user_is_bot_owner(ctx: &Context, user_id: u64) -> bool {
+    let data = ctx.data.read().await;
+    data.get::<BotOwnersContainer>()
+        .map(|owners| owners.contains(&user_id))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_adds_ellipsis() {
+        assert_eq!(truncate("hello", 5), "hello");
+        assert_eq!(truncate("hello world", 5), "hell…");
+        assert_eq!(truncate("ŝŝŝŝŝŝ", 3), "ŝŝ…");
+    }
+
+    #[test]
+    fn case_fold_ignores_case() {
+        assert_eq!(compatibility_case_fold("HeLLo"), "hello");
+        assert_eq!(
+            compatibility_case_fold("ΣΊΣΥΦΟΣ"),
+            compatibility_case_fold("σίσυφος")
+        );
+    }
+
+    #[test]
+    fn case_fold_expands_special_cases() {
+        assert_eq!(
+            compatibility_case_fold("Straße"),
+            compatibility_case_fold("STRASSE")
+        );
+        assert_eq!(compatibility_case_fold("ǅ"), compatibility_case_fold("dž"));
+    }
+
+    #[test]
+    fn case_fold_unifies_compatibility_forms() {
+        assert_eq!(compatibility_case_fold("ﬁle"), "file");
+        assert_eq!(compatibility_case_fold("ＡＢＣ"), "abc");
+        assert_eq!(compatibility_case_fold("①"), "1");
+    }
+
+    #[test]
+    fn case_fold_unifies_canonical_forms() {
+        // Precomposed, decomposed and the Ångström sign
+        let folded = compatibility_case_fold("\u{C5}");
+        assert_eq!(compatibility_case_fold("A\u{30A}"), folded);
+        assert_eq!(compatibility_case_fold("\u{212B}"), folded);
+        assert_eq!(compatibility_case_fold("\u{E5}"), folded);
+    }
+
+    #[test]
+    fn case_fold_keeps_wildcards_literal() {
+        assert_eq!(compatibility_case_fold("%"), "%");
+        assert_ne!(
+            compatibility_case_fold("a_c"),
+            compatibility_case_fold("abc")
+        );
+    }
+}